@@ -1,25 +1,657 @@
-use clap::Parser;
+use clap::{ArgAction, Parser};
+use encoding_rs::Encoding;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-use std::{fmt, fs, str::FromStr};
+use std::{
+    fmt, fs, io,
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use crate::{process_csv, CmdExector};
+use crate::{
+    create_output, process_csv, process_csv_decrypt, process_csv_diff, process_csv_encrypt,
+    process_csv_from, process_csv_generate_key, process_csv_join, process_csv_pivot,
+    process_csv_show, process_csv_split, process_csv_stats, process_csv_validate,
+    process_csv_write_quarantine, read_content, CmdExector, CsvConvertOpts, CsvDialect, CsvOutput,
+    CsvSchema, JoinOpts, PivotOpts, Predicate, SortKey, SplitBy, SplitOpts, TableOpts,
+    TransformRule,
+};
 
-use super::verify_file;
+use super::{verify_file, verify_input};
 
 // rcli csv -i input.csv -o output.json --header -d ','
 
 #[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct CsvOpts {
-    #[arg(short, long, value_parser = verify_file)]
-    pub input: String,
-    #[arg(short, long)] //"output.json".into()
+    #[command(subcommand)]
+    pub cmd: Option<CsvSubCommand>,
+    #[arg(
+        short,
+        long,
+        value_parser = verify_input,
+        default_value = "-",
+        help = "input file or glob pattern, can be repeated, the rows of all files are \
+                written under the union of their headers"
+    )]
+    pub input: Vec<String>,
+    #[arg(long, help = "add a column with the file each row was read from")]
+    pub source_column: Option<String>,
+    #[command(flatten)]
+    pub dialect: CsvDialectOpts,
+    #[command(flatten)]
+    pub out: CsvOutputOpts,
+    #[command(flatten)]
+    pub transform: CsvTransformOpts,
+    #[arg(
+        long = "type",
+        value_parser = parse_column_type_override,
+        help = "force a column type, e.g. \"Kit Number=int\", can be repeated"
+    )]
+    pub types: Vec<(String, ColumnType)>,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "only output these columns, in this order, e.g. \"Kit Number,Name\""
+    )]
+    pub columns: Vec<String>,
+    #[arg(
+        long = "where",
+        value_parser = parse_predicate,
+        help = "only keep rows matching e.g. 'Position == \"Goalkeeper\"', can be repeated"
+    )]
+    pub filters: Vec<Predicate>,
+    #[arg(
+        long,
+        value_parser = parse_sort_key,
+        value_delimiter = ',',
+        help = "sort rows by columns, e.g. \"Kit Number:desc,Name\""
+    )]
+    pub sort_by: Vec<SortKey>,
+    #[arg(
+        long,
+        help = "only output the first N rows after filtering and sorting"
+    )]
+    pub limit: Option<usize>,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "nest rows by column values, e.g. \"Nationality,Position\", json or yaml only"
+    )]
+    pub group_by: Vec<String>,
+    #[arg(
+        long,
+        value_parser = verify_file,
+        help = "schema file, rows violating it are skipped and reported on stderr"
+    )]
+    pub schema: Option<String>,
+    #[arg(
+        long,
+        value_parser = parse_on_error,
+        default_value = "fail",
+        help = "rows that fail to parse: fail, skip or quarantine to a side file"
+    )]
+    pub on_error: OnError,
+    #[arg(
+        long,
+        help = "side file of rows that failed to parse with their line, reason and raw record, \
+                defaults to the output with .rejected.csv extension"
+    )]
+    pub quarantine: Option<String>,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "print an aligned table instead of converting"
+    )]
+    pub show: bool,
+    #[arg(
+        long,
+        requires = "show",
+        conflicts_with = "tail",
+        help = "only show the first N rows"
+    )]
+    pub head: Option<usize>,
+    #[arg(long, requires = "show", help = "only show the last N rows")]
+    pub tail: Option<usize>,
+    #[arg(
+        long,
+        default_value_t = 30,
+        help = "max display width of a cell in --show"
+    )]
+    pub max_width: usize,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "wrap long cells in --show instead of truncating"
+    )]
+    pub wrap: bool,
+}
+
+// where and how converted rows are written, shared by conversion and join
+#[derive(Debug, Parser)]
+pub struct CsvOutputOpts {
+    #[arg(short, long, help = "output file, \"-\" writes to stdout")] //"output.json".into()
     pub output: Option<String>,
     #[arg(short, long,  value_parser = parse_format, default_value = "json")]
     pub format: OutputFormat,
-    #[arg(short, long, default_value_t = ',')]
-    pub delimiter: char,
-    #[arg(long, default_value_t = true)]
+    #[arg(
+        long,
+        default_value = "rows",
+        help = "table name of the [[rows]] array in TOML output"
+    )]
+    pub toml_table: String,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "write empty cells as \"\" in TOML output"
+    )]
+    pub toml_keep_empty: bool,
+    #[arg(long, help = "message name in the generated .proto schema")]
+    pub proto_message: Option<String>,
+    #[arg(
+        long,
+        help = "where to write the .proto schema, defaults to the output with .proto extension"
+    )]
+    pub proto_schema: Option<String>,
+    #[arg(long, default_value_t = false, help = "emit every cell as a string")]
+    pub no_infer: bool,
+    #[arg(
+        long,
+        value_parser = parse_encoding,
+        help = "re-encode the output, e.g. gbk or utf-16le, defaults to utf-8"
+    )]
+    pub output_encoding: Option<&'static Encoding>,
+    #[arg(
+        long = "mask",
+        value_parser = parse_mask,
+        help = "mask an output column, e.g. \"Name=blake3\", redact or year-only, can be repeated"
+    )]
+    pub masks: Vec<(String, MaskKind)>,
+    #[arg(
+        long,
+        value_parser = verify_file,
+        help = "key file of blake3 masks, the same key gives the same pseudonyms"
+    )]
+    pub mask_key: Option<String>,
+    #[arg(
+        long,
+        value_parser = parse_sql_dialect,
+        default_value = "sqlite",
+        help = "SQL output dialect: sqlite, postgres or mysql"
+    )]
+    pub sql_dialect: SqlDialect,
+    #[arg(
+        long,
+        help = "table name in SQL output, defaults to the input file name"
+    )]
+    pub sql_table: Option<String>,
+    #[arg(
+        long,
+        default_value_t = 500,
+        help = "rows per INSERT statement in SQL output"
+    )]
+    pub sql_batch: usize,
+    #[arg(
+        long,
+        help = "insert the rows into a new table of this SQLite database instead of writing output"
+    )]
+    pub sqlite_db: Option<String>,
+}
+
+impl CsvOutputOpts {
+    pub fn convert_opts(&self) -> anyhow::Result<CsvConvertOpts> {
+        let mask_key = match &self.mask_key {
+            Some(path) => Some(read_content(path)?),
+            None => None,
+        };
+        Ok(CsvConvertOpts {
+            format: self.format,
+            toml_table: self.toml_table.clone(),
+            toml_keep_empty: self.toml_keep_empty,
+            proto_message: self.proto_message.clone(),
+            infer: !self.no_infer,
+            output_encoding: self.output_encoding,
+            masks: self.masks.clone(),
+            mask_key,
+            sql_dialect: self.sql_dialect,
+            sql_table: self.sql_table.clone(),
+            sql_batch: self.sql_batch,
+            sqlite_db: self.sqlite_db.clone(),
+            ..Default::default()
+        })
+    }
+
+    // next to the output, or in the working directory for stdout and sqlite
+    pub fn quarantine_path(&self) -> PathBuf {
+        match &self.output {
+            Some(output) if output != "-" && self.sqlite_db.is_none() => {
+                Path::new(output).with_extension("rejected.csv")
+            }
+            Some(_) => "rejected.csv".into(),
+            None => Path::new("output").with_extension("rejected.csv"),
+        }
+    }
+
+    // resolves the default output file and writes the .proto schema next to proto output
+    pub fn write(
+        &self,
+        convert: impl FnOnce(&mut dyn Write) -> anyhow::Result<CsvOutput>,
+    ) -> anyhow::Result<CsvOutput> {
+        if self.sqlite_db.is_some() {
+            return convert(&mut io::sink());
+        }
+        let output = if let Some(output) = &self.output {
+            output.clone()
+        } else {
+            format!("output.{}", self.format.extension())
+        };
+        let schema_path = match &self.proto_schema {
+            Some(path) => path.into(),
+            None if output == "-" => "output.proto".into(),
+            None => Path::new(&output).with_extension("proto"),
+        };
+        if let OutputFormat::Proto = self.format {
+            if schema_path == Path::new(&output) {
+                anyhow::bail!(
+                    "proto schema and data can not both be written to {}",
+                    output
+                );
+            }
+        }
+
+        // println!("{}", output);
+        let mut writer = create_output(&output)?;
+        let result = convert(&mut writer)?;
+        writer.finish()?;
+        if let Some(schema) = &result.schema {
+            fs::write(schema_path, schema)?;
+        }
+        Ok(result)
+    }
+}
+
+// cell rewrites applied before anything else, shared by conversion and split
+#[derive(Debug, Parser)]
+pub struct CsvTransformOpts {
+    #[arg(
+        long = "transform",
+        value_parser = parse_transform,
+        help = "rewrite a column, e.g. 'DOB=date:%b %d, %Y->%Y-%m-%d', trim, lower, upper, \
+                regex_replace:<re>-><to> or split:<sep>-><col>,<col>, can be repeated"
+    )]
+    pub rules: Vec<TransformRule>,
+    #[arg(
+        long = "transforms",
+        value_parser = verify_file,
+        help = "YAML file of transform rules, applied before --transform"
+    )]
+    pub file: Option<String>,
+}
+
+impl CsvTransformOpts {
+    pub fn rules(&self) -> anyhow::Result<Vec<TransformRule>> {
+        let mut rules = match &self.file {
+            Some(path) => TransformRule::load(path)?,
+            None => Vec::new(),
+        };
+        rules.extend(self.rules.iter().cloned());
+        Ok(rules)
+    }
+}
+
+// how the input csv is parsed, shared by every csv command that reads csv
+#[derive(Debug, Parser)]
+pub struct CsvDialectOpts {
+    #[arg(
+        short,
+        long,
+        value_parser = parse_dialect_char,
+        default_value = ",",
+        help = "field delimiter, use '\\t' or 'tab' for TSV"
+    )]
+    pub delimiter: u8,
+    #[arg(long, value_parser = parse_dialect_char, default_value = "\"")]
+    pub quote: u8,
+    #[arg(
+        long,
+        value_parser = parse_dialect_char,
+        help = "escape character for quotes inside quoted fields, e.g. '\\'"
+    )]
+    pub escape: Option<u8>,
+    #[arg(
+        long,
+        value_parser = parse_dialect_char,
+        help = "skip lines starting with this character"
+    )]
+    pub comment: Option<u8>,
+    #[arg(
+        long,
+        value_parser = parse_trim,
+        default_value = "none",
+        help = "trim whitespace: none, headers, fields or all"
+    )]
+    pub trim: CsvTrim,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "allow rows with a different number of fields"
+    )]
+    pub flexible: bool,
+    #[arg(
+        long,
+        default_value_t = true,
+        action = ArgAction::Set,
+        num_args = 0..=1,
+        default_missing_value = "true",
+        help = "first row is a header, --header false uses col_1..col_n keys"
+    )]
     pub header: bool,
+    #[arg(
+        long,
+        value_parser = parse_encoding,
+        help = "input encoding, e.g. gbk, windows-1252 or utf-16le, detected when omitted"
+    )]
+    pub encoding: Option<&'static Encoding>,
+}
+
+impl CsvDialectOpts {
+    pub fn dialect(&self) -> CsvDialect {
+        CsvDialect {
+            delimiter: self.delimiter,
+            quote: self.quote,
+            escape: self.escape,
+            comment: self.comment,
+            trim: self.trim,
+            flexible: self.flexible,
+            has_headers: self.header,
+            encoding: self.encoding,
+        }
+    }
+}
+
+#[derive(Debug, Parser)]
+pub enum CsvSubCommand {
+    #[command(about = "Convert JSON, YAML or NDJSON records back to CSV")]
+    From(CsvFromOpts),
+    #[command(about = "Report per-column statistics of a CSV file")]
+    Stats(CsvStatsOpts),
+    #[command(about = "Join two CSV files on key columns")]
+    Join(CsvJoinOpts),
+    #[command(about = "Show added, removed and changed rows between two CSV files")]
+    Diff(CsvDiffOpts),
+    #[command(about = "Check a CSV file against a schema and report every violation")]
+    Validate(CsvValidateOpts),
+    #[command(about = "Split a CSV file into one file per column value or per N rows")]
+    Split(CsvSplitOpts),
+    #[command(about = "Encrypt columns in place with XChaCha20-Poly1305, one nonce per cell")]
+    Encrypt(CsvEncryptOpts),
+    #[command(about = "Decrypt columns written by csv encrypt")]
+    Decrypt(CsvDecryptOpts),
+    #[command(about = "Crosstab of a column aggregated by row and column values")]
+    Pivot(CsvPivotOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvFromOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(
+        short,
+        long,
+        default_value = "output.csv",
+        help = "output file, \"-\" writes to stdout"
+    )]
+    pub output: String,
+    #[arg(
+        short,
+        long,
+        value_parser = parse_format,
+        help = "json, yaml or ndjson, detected from the input extension when omitted"
+    )]
+    pub format: Option<OutputFormat>,
+    #[arg(short, long, value_parser = parse_dialect_char, default_value = ",")]
+    pub delimiter: u8,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvStatsOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(
+        short,
+        long,
+        value_parser = parse_report_format,
+        default_value = "table",
+        help = "table or json"
+    )]
+    pub format: ReportFormat,
+    #[arg(
+        long,
+        default_value_t = 3,
+        help = "how many of the most frequent values to list"
+    )]
+    pub top: usize,
+    #[command(flatten)]
+    pub dialect: CsvDialectOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvJoinOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-", help = "left file")]
+    pub input: String,
+    #[arg(long, value_parser = verify_file, help = "right file")]
+    pub with: String,
+    #[arg(
+        long,
+        required = true,
+        value_delimiter = ',',
+        help = "key columns present in both files, e.g. \"Name\""
+    )]
+    pub on: Vec<String>,
+    #[arg(
+        long,
+        value_parser = parse_join_kind,
+        default_value = "inner",
+        help = "inner, left or full"
+    )]
+    pub how: JoinKind,
+    #[arg(
+        long,
+        default_value = "left_",
+        help = "prefix for left columns whose name is also used in the right file"
+    )]
+    pub left_prefix: String,
+    #[arg(
+        long,
+        default_value = "right_",
+        help = "prefix for right columns whose name is also used in the left file"
+    )]
+    pub right_prefix: String,
+    #[command(flatten)]
+    pub dialect: CsvDialectOpts,
+    #[command(flatten)]
+    pub out: CsvOutputOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvDiffOpts {
+    #[arg(value_parser = verify_file, help = "the older snapshot")]
+    pub old: String,
+    #[arg(value_parser = verify_file, help = "the newer snapshot")]
+    pub new: String,
+    #[arg(
+        short,
+        long,
+        required = true,
+        value_delimiter = ',',
+        help = "columns identifying a row in both files, e.g. \"Name\""
+    )]
+    pub key: Vec<String>,
+    #[arg(
+        short,
+        long,
+        value_parser = parse_report_format,
+        default_value = "table",
+        help = "table or json"
+    )]
+    pub format: ReportFormat,
+    #[command(flatten)]
+    pub dialect: CsvDialectOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvValidateOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(
+        long,
+        value_parser = verify_file,
+        help = "YAML or JSON schema with required, type, pattern, enum and unique rules"
+    )]
+    pub schema: String,
+    #[arg(
+        short,
+        long,
+        value_parser = parse_report_format,
+        default_value = "table",
+        help = "table or json"
+    )]
+    pub format: ReportFormat,
+    #[command(flatten)]
+    pub dialect: CsvDialectOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvSplitOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(
+        long,
+        conflicts_with = "rows",
+        required_unless_present = "rows",
+        help = "write one file per distinct value of this column"
+    )]
+    pub by: Option<String>,
+    #[arg(long, help = "write files of at most N rows")]
+    pub rows: Option<usize>,
+    #[arg(short, long, value_parser = parse_format, default_value = "csv")]
+    pub format: OutputFormat,
+    #[arg(long, default_value = ".", help = "directory the files are written to")]
+    pub output_dir: String,
+    #[arg(
+        long,
+        help = "file name with {stem}, {value}, {index} and {ext}, e.g. \"{stem}_{value}.{ext}\""
+    )]
+    pub template: Option<String>,
+    #[arg(long, default_value_t = false, help = "emit every cell as a string")]
+    pub no_infer: bool,
+    #[arg(
+        long,
+        value_parser = parse_encoding,
+        help = "re-encode the files, e.g. gbk or utf-16le, defaults to utf-8"
+    )]
+    pub output_encoding: Option<&'static Encoding>,
+    #[command(flatten)]
+    pub transform: CsvTransformOpts,
+    #[command(flatten)]
+    pub dialect: CsvDialectOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvEncryptOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(
+        short,
+        long,
+        default_value = "-",
+        help = "output file, \"-\" writes to stdout"
+    )]
+    pub output: String,
+    #[arg(
+        long,
+        required = true,
+        value_delimiter = ',',
+        help = "columns to encrypt, e.g. \"Name,DOB\""
+    )]
+    pub columns: Vec<String>,
+    #[arg(short, long, help = "file with a base64 encoded 32 byte key")]
+    pub key: String,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "write a new random key to --key first, readable only by its owner; \
+                an existing file is never replaced"
+    )]
+    pub generate_key: bool,
+    #[command(flatten)]
+    pub dialect: CsvDialectOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvDecryptOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(
+        short,
+        long,
+        default_value = "-",
+        help = "output file, \"-\" writes to stdout"
+    )]
+    pub output: String,
+    #[arg(
+        long,
+        required = true,
+        value_delimiter = ',',
+        help = "encrypted columns, e.g. \"Name,DOB\""
+    )]
+    pub columns: Vec<String>,
+    #[arg(short, long, value_parser = verify_file, help = "the key the columns were encrypted with")]
+    pub key: String,
+    #[command(flatten)]
+    pub dialect: CsvDialectOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvPivotOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(
+        short,
+        long,
+        default_value = "-",
+        help = "output file, \"-\" writes to stdout"
+    )]
+    pub output: String,
+    #[arg(
+        long,
+        required = true,
+        value_delimiter = ',',
+        help = "one output row per distinct value of these columns, e.g. \"Nationality\""
+    )]
+    pub rows: Vec<String>,
+    #[arg(long, help = "one output column per distinct value of this column")]
+    pub cols: String,
+    #[arg(long, help = "column to aggregate, count counts rows without it")]
+    pub value: Option<String>,
+    #[arg(
+        long,
+        value_parser = parse_aggregate,
+        default_value = "count",
+        help = "count, sum, avg, min or max"
+    )]
+    pub agg: Aggregate,
+    #[arg(
+        short,
+        long,
+        value_parser = parse_pivot_format,
+        default_value = "table",
+        help = "table or csv"
+    )]
+    pub format: PivotFormat,
+    #[command(flatten)]
+    pub dialect: CsvDialectOpts,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -28,6 +660,28 @@ pub enum OutputFormat {
     Yaml,
     Toml,
     Proto,
+    Ndjson,
+    Csv,
+    Sql,
+    Markdown,
+    Html,
+}
+
+impl OutputFormat {
+    // file extension of the format, proto data is .pb since .proto is taken by the schema
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Proto => "pb",
+            OutputFormat::Markdown => "md",
+            format => format.into(),
+        }
+    }
+
+    // whether rows can be appended to finished output, json arrays and html pages end
+    // with closing lines
+    pub fn appendable(self) -> bool {
+        !matches!(self, OutputFormat::Json | OutputFormat::Html)
+    }
 }
 
 // fn parse_format(format: &str) -> Result<OutputFormat, &'static str> {
@@ -43,6 +697,431 @@ fn parse_format(format: &str) -> Result<OutputFormat, &'static str> {
     format.parse()
 }
 
+#[derive(Debug, Clone, Copy)]
+pub enum JoinKind {
+    Inner,
+    Left,
+    Full,
+}
+
+fn parse_join_kind(kind: &str) -> Result<JoinKind, &'static str> {
+    kind.parse()
+}
+
+impl FromStr for JoinKind {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "inner" => Ok(JoinKind::Inner),
+            "left" => Ok(JoinKind::Left),
+            "full" | "outer" => Ok(JoinKind::Full),
+            _ => Err("Invalid join, must be inner, left or full"),
+        }
+    }
+}
+
+impl From<JoinKind> for &'static str {
+    fn from(value: JoinKind) -> Self {
+        match value {
+            JoinKind::Inner => "inner",
+            JoinKind::Left => "left",
+            JoinKind::Full => "full",
+        }
+    }
+}
+
+impl fmt::Display for JoinKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+// how a masked output cell is replaced
+#[derive(Debug, Clone, Copy)]
+pub enum MaskKind {
+    // keyed hash, stable across files so masked columns can still be joined
+    Blake3,
+    Redact,
+    YearOnly,
+}
+
+impl FromStr for MaskKind {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "blake3" => Ok(MaskKind::Blake3),
+            "redact" => Ok(MaskKind::Redact),
+            "year-only" => Ok(MaskKind::YearOnly),
+            _ => Err("Invalid mask, must be blake3, redact or year-only"),
+        }
+    }
+}
+
+impl From<MaskKind> for &'static str {
+    fn from(value: MaskKind) -> Self {
+        match value {
+            MaskKind::Blake3 => "blake3",
+            MaskKind::Redact => "redact",
+            MaskKind::YearOnly => "year-only",
+        }
+    }
+}
+
+impl fmt::Display for MaskKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+// quoting, escaping and column types of sql output
+#[derive(Debug, Clone, Copy)]
+pub enum SqlDialect {
+    Sqlite,
+    Postgres,
+    Mysql,
+}
+
+fn parse_sql_dialect(dialect: &str) -> Result<SqlDialect, &'static str> {
+    dialect.parse()
+}
+
+impl FromStr for SqlDialect {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sqlite" => Ok(SqlDialect::Sqlite),
+            "postgres" | "postgresql" | "pg" => Ok(SqlDialect::Postgres),
+            "mysql" => Ok(SqlDialect::Mysql),
+            _ => Err("Invalid SQL dialect, must be sqlite, postgres or mysql"),
+        }
+    }
+}
+
+impl From<SqlDialect> for &'static str {
+    fn from(value: SqlDialect) -> Self {
+        match value {
+            SqlDialect::Sqlite => "sqlite",
+            SqlDialect::Postgres => "postgres",
+            SqlDialect::Mysql => "mysql",
+        }
+    }
+}
+
+impl fmt::Display for SqlDialect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+// what happens to rows that fail to parse, e.g. ragged rows or invalid UTF-8
+#[derive(Debug, Clone, Copy)]
+pub enum OnError {
+    Fail,
+    Skip,
+    // skipped and written to a side file with their line and reason
+    Quarantine,
+}
+
+fn parse_on_error(on_error: &str) -> Result<OnError, &'static str> {
+    on_error.parse()
+}
+
+impl FromStr for OnError {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fail" => Ok(OnError::Fail),
+            "skip" => Ok(OnError::Skip),
+            "quarantine" => Ok(OnError::Quarantine),
+            _ => Err("Invalid on-error, must be fail, skip or quarantine"),
+        }
+    }
+}
+
+impl From<OnError> for &'static str {
+    fn from(value: OnError) -> Self {
+        match value {
+            OnError::Fail => "fail",
+            OnError::Skip => "skip",
+            OnError::Quarantine => "quarantine",
+        }
+    }
+}
+
+impl fmt::Display for OnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+// how the values of a pivot cell are combined
+#[derive(Debug, Clone, Copy)]
+pub enum Aggregate {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+fn parse_aggregate(agg: &str) -> Result<Aggregate, &'static str> {
+    agg.parse()
+}
+
+impl FromStr for Aggregate {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "count" => Ok(Aggregate::Count),
+            "sum" => Ok(Aggregate::Sum),
+            "avg" | "mean" => Ok(Aggregate::Avg),
+            "min" => Ok(Aggregate::Min),
+            "max" => Ok(Aggregate::Max),
+            _ => Err("Invalid aggregate, must be count, sum, avg, min or max"),
+        }
+    }
+}
+
+impl From<Aggregate> for &'static str {
+    fn from(value: Aggregate) -> Self {
+        match value {
+            Aggregate::Count => "count",
+            Aggregate::Sum => "sum",
+            Aggregate::Avg => "avg",
+            Aggregate::Min => "min",
+            Aggregate::Max => "max",
+        }
+    }
+}
+
+impl fmt::Display for Aggregate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+// how a pivot is printed
+#[derive(Debug, Clone, Copy)]
+pub enum PivotFormat {
+    Table,
+    Csv,
+}
+
+fn parse_pivot_format(format: &str) -> Result<PivotFormat, &'static str> {
+    format.parse()
+}
+
+impl FromStr for PivotFormat {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(PivotFormat::Table),
+            "csv" => Ok(PivotFormat::Csv),
+            _ => Err("Invalid pivot format, must be table or csv"),
+        }
+    }
+}
+
+impl From<PivotFormat> for &'static str {
+    fn from(value: PivotFormat) -> Self {
+        match value {
+            PivotFormat::Table => "table",
+            PivotFormat::Csv => "csv",
+        }
+    }
+}
+
+impl fmt::Display for PivotFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+// how reports such as stats are printed
+#[derive(Debug, Clone, Copy)]
+pub enum ReportFormat {
+    Table,
+    Json,
+}
+
+fn parse_report_format(format: &str) -> Result<ReportFormat, &'static str> {
+    format.parse()
+}
+
+impl FromStr for ReportFormat {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(ReportFormat::Table),
+            "json" => Ok(ReportFormat::Json),
+            _ => Err("Invalid report format"),
+        }
+    }
+}
+
+impl From<ReportFormat> for &'static str {
+    fn from(value: ReportFormat) -> Self {
+        match value {
+            ReportFormat::Table => "table",
+            ReportFormat::Json => "json",
+        }
+    }
+}
+
+impl fmt::Display for ReportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum CsvTrim {
+    None,
+    Headers,
+    Fields,
+    All,
+}
+
+fn parse_trim(trim: &str) -> Result<CsvTrim, &'static str> {
+    trim.parse()
+}
+
+impl FromStr for CsvTrim {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(CsvTrim::None),
+            "headers" => Ok(CsvTrim::Headers),
+            "fields" => Ok(CsvTrim::Fields),
+            "all" => Ok(CsvTrim::All),
+            _ => Err("Invalid trim"),
+        }
+    }
+}
+
+impl From<CsvTrim> for &'static str {
+    fn from(value: CsvTrim) -> Self {
+        match value {
+            CsvTrim::None => "none",
+            CsvTrim::Headers => "headers",
+            CsvTrim::Fields => "fields",
+            CsvTrim::All => "all",
+        }
+    }
+}
+
+impl fmt::Display for CsvTrim {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    String,
+    Int,
+    Float,
+    Bool,
+    Date,
+    DateTime,
+}
+
+impl FromStr for ColumnType {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "string" => Ok(ColumnType::String),
+            "int" => Ok(ColumnType::Int),
+            "float" => Ok(ColumnType::Float),
+            "bool" => Ok(ColumnType::Bool),
+            "date" => Ok(ColumnType::Date),
+            "datetime" => Ok(ColumnType::DateTime),
+            _ => Err("Invalid column type"),
+        }
+    }
+}
+
+impl From<ColumnType> for &'static str {
+    fn from(value: ColumnType) -> Self {
+        match value {
+            ColumnType::String => "string",
+            ColumnType::Int => "int",
+            ColumnType::Float => "float",
+            ColumnType::Bool => "bool",
+            ColumnType::Date => "date",
+            ColumnType::DateTime => "datetime",
+        }
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+// "int" in reports and schema files, like on the command line
+impl Serialize for ColumnType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str((*self).into())
+    }
+}
+
+impl<'de> Deserialize<'de> for ColumnType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+// "Kit Number=int", the column name may itself contain '='
+fn parse_column_type_override(s: &str) -> Result<(String, ColumnType), &'static str> {
+    let (column, ty) = s.rsplit_once('=').ok_or("Must be <column>=<type>")?;
+    Ok((column.to_owned(), ty.trim().parse()?))
+}
+
+// "Name=blake3", like --type the column name may itself contain '='
+fn parse_mask(s: &str) -> Result<(String, MaskKind), &'static str> {
+    let (column, kind) = s.rsplit_once('=').ok_or("Must be <column>=<mask>")?;
+    Ok((column.to_owned(), kind.trim().parse()?))
+}
+
+// operators: ==, !=, <, <=, >, >=, =~ (regex), !~ (regex does not match)
+fn parse_predicate(s: &str) -> Result<Predicate, &'static str> {
+    s.parse()
+}
+
+fn parse_sort_key(s: &str) -> Result<SortKey, &'static str> {
+    s.parse()
+}
+
+fn parse_transform(s: &str) -> Result<TransformRule, &'static str> {
+    s.parse()
+}
+
+// the csv reader works on bytes, so dialect characters must be ascii
+fn parse_dialect_char(c: &str) -> Result<u8, &'static str> {
+    match c {
+        "\\t" | "tab" => Ok(b'\t'),
+        _ => {
+            let mut chars = c.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if c.is_ascii() => Ok(c as u8),
+                _ => Err("Must be a single ascii character"),
+            }
+        }
+    }
+}
+
+// WHATWG labels, e.g. gbk, gb18030, windows-1252, latin1, shift_jis, utf-16le
+fn parse_encoding(label: &str) -> Result<&'static Encoding, &'static str> {
+    let label = match label.to_ascii_lowercase().as_str() {
+        "latin-1" => "latin1".to_owned(),
+        label => label.to_owned(),
+    };
+    Encoding::for_label(label.as_bytes()).ok_or("Unknown encoding, e.g. utf-8, gbk, latin1")
+}
+
 // impl fmt::Display for OutputFormat {
 //     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 //         match self {
@@ -68,6 +1147,11 @@ impl From<OutputFormat> for &'static str {
             OutputFormat::Yaml => "yaml",
             OutputFormat::Toml => "toml",
             OutputFormat::Proto => "proto",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Sql => "sql",
+            OutputFormat::Markdown => "markdown",
+            OutputFormat::Html => "html",
         }
     }
 }
@@ -80,6 +1164,11 @@ impl FromStr for OutputFormat {
             "yaml" => Ok(OutputFormat::Yaml),
             "toml" => Ok(OutputFormat::Toml),
             "proto" => Ok(OutputFormat::Proto),
+            "ndjson" | "jsonl" => Ok(OutputFormat::Ndjson),
+            "csv" => Ok(OutputFormat::Csv),
+            "sql" => Ok(OutputFormat::Sql),
+            "markdown" | "md" => Ok(OutputFormat::Markdown),
+            "html" => Ok(OutputFormat::Html),
             _ => Err("Invalid format"),
         }
     }
@@ -87,18 +1176,269 @@ impl FromStr for OutputFormat {
 
 impl CmdExector for CsvOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        let json = process_csv(&self.input, &self.format)?;
-        let output = if let Some(output) = self.output {
-            output
-        } else {
-            format!("output.{}", &self.format)
+        if let Some(cmd) = self.cmd {
+            return cmd.execute().await;
+        }
+        let dialect = self.dialect.dialect();
+        let inputs = expand_inputs(&self.input)?;
+        if self.show {
+            let [input] = inputs.as_slice() else {
+                anyhow::bail!("--show reads a single input");
+            };
+            let opts = TableOpts {
+                head: self.head,
+                tail: self.tail,
+                max_width: self.max_width,
+                wrap: self.wrap,
+            };
+            let mut writer = create_output("-")?;
+            process_csv_show(input, &dialect, &opts, &mut writer)?;
+            writer.finish()?;
+            return Ok(());
+        }
+        let opts = CsvConvertOpts {
+            types: self.types,
+            columns: self.columns,
+            filters: self.filters,
+            sort_by: self.sort_by,
+            limit: self.limit,
+            group_by: self.group_by,
+            transforms: self.transform.rules()?,
+            schema: match &self.schema {
+                Some(path) => Some(CsvSchema::load(path)?),
+                None => None,
+            },
+            on_error: self.on_error,
+            source_column: self.source_column,
+            ..self.out.convert_opts()?
         };
-        // println!("{}", output);
-        fs::write(output, json)?;
+        let result = self
+            .out
+            .write(|writer| process_csv(&inputs, &dialect, &opts, writer))?;
+        match self.on_error {
+            OnError::Quarantine if !result.malformed.is_empty() => {
+                let path = match &self.quarantine {
+                    Some(path) => path.into(),
+                    None => self.out.quarantine_path(),
+                };
+                let mut writer = create_output(&path.to_string_lossy())?;
+                process_csv_write_quarantine(&result.malformed, &dialect, &mut writer)?;
+                writer.finish()?;
+                eprintln!(
+                    "{} rows quarantined to {}",
+                    result.malformed.len(),
+                    path.display()
+                );
+            }
+            _ => {
+                for row in result.malformed.iter() {
+                    eprintln!("skipped {}", row);
+                }
+            }
+        }
+        // schema violations are reported apart from rows that failed to parse
+        for violation in result.rejected.iter() {
+            eprintln!("skipped {}", violation);
+        }
+        Ok(())
+    }
+}
+
+// patterns are replaced by the files they match in path order, a pattern matching nothing is an error
+fn expand_inputs(inputs: &[String]) -> anyhow::Result<Vec<String>> {
+    let mut paths = Vec::new();
+    for input in inputs {
+        if !input.contains(['*', '?', '[']) {
+            paths.push(input.clone());
+            continue;
+        }
+        let matched = glob::glob(input)?
+            .map(|p| Ok(p?.to_string_lossy().into_owned()))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if matched.is_empty() {
+            anyhow::bail!("no file matches {}", input);
+        }
+        paths.extend(matched);
+    }
+    Ok(paths)
+}
+
+impl CmdExector for CsvFromOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let format = match self.format {
+            Some(format) => format,
+            None => match Path::new(&self.input).extension().and_then(|e| e.to_str()) {
+                Some("yaml") | Some("yml") => OutputFormat::Yaml,
+                Some("ndjson") | Some("jsonl") => OutputFormat::Ndjson,
+                _ => OutputFormat::Json,
+            },
+        };
+        let mut writer = create_output(&self.output)?;
+        process_csv_from(&self.input, format, self.delimiter, &mut writer)?;
+        writer.finish()?;
         Ok(())
     }
 }
 
+impl CmdExector for CsvStatsOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let stats = process_csv_stats(&self.input, &self.dialect.dialect(), self.top)?;
+        match self.format {
+            ReportFormat::Table => print!("{}", stats.to_table()),
+            ReportFormat::Json => println!("{}", serde_json::to_string_pretty(&stats)?),
+        }
+        Ok(())
+    }
+}
+
+impl CmdExector for CsvJoinOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let join = JoinOpts {
+            on: self.on,
+            kind: self.how,
+            left_prefix: self.left_prefix,
+            right_prefix: self.right_prefix,
+        };
+        let dialect = self.dialect.dialect();
+        let opts = self.out.convert_opts()?;
+        self.out.write(|writer| {
+            process_csv_join(&self.input, &self.with, &dialect, &join, &opts, writer)
+        })?;
+        Ok(())
+    }
+}
+
+impl CmdExector for CsvDiffOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let diff = process_csv_diff(&self.old, &self.new, &self.dialect.dialect(), &self.key)?;
+        match self.format {
+            ReportFormat::Table => print!("{}", diff.to_table()),
+            ReportFormat::Json => println!("{}", serde_json::to_string_pretty(&diff)?),
+        }
+        Ok(())
+    }
+}
+
+impl CmdExector for CsvValidateOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let schema = CsvSchema::load(&self.schema)?;
+        let violations = process_csv_validate(&self.input, &self.dialect.dialect(), &schema)?;
+        match self.format {
+            ReportFormat::Table => {
+                for violation in violations.iter() {
+                    println!("{}", violation);
+                }
+            }
+            ReportFormat::Json => println!("{}", serde_json::to_string_pretty(&violations)?),
+        }
+        if !violations.is_empty() {
+            anyhow::bail!("{} violations found in {}", violations.len(), self.input);
+        }
+        Ok(())
+    }
+}
+
+impl CmdExector for CsvSplitOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let by = match (self.by, self.rows) {
+            (Some(column), _) => SplitBy::Column(column),
+            (None, Some(rows)) => SplitBy::Rows(rows),
+            (None, None) => anyhow::bail!("either --by or --rows is required"),
+        };
+        let split = SplitOpts {
+            by,
+            template: self.template,
+            output_dir: self.output_dir,
+        };
+        let opts = CsvConvertOpts {
+            format: self.format,
+            infer: !self.no_infer,
+            output_encoding: self.output_encoding,
+            transforms: self.transform.rules()?,
+            ..Default::default()
+        };
+        let files = process_csv_split(&self.input, &self.dialect.dialect(), &split, &opts)?;
+        for file in files.iter() {
+            println!("{}\t{} rows", file.path.display(), file.rows);
+        }
+        Ok(())
+    }
+}
+
+impl CmdExector for CsvEncryptOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        if self.generate_key {
+            process_csv_generate_key(&self.key)?;
+            eprintln!(
+                "Make sure to keep {} to decrypt the columns later!",
+                self.key
+            );
+        }
+        let key = read_content(&self.key)?;
+        let mut writer = create_output(&self.output)?;
+        process_csv_encrypt(
+            &self.input,
+            &self.dialect.dialect(),
+            &self.columns,
+            &key,
+            &mut writer,
+        )?;
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+impl CmdExector for CsvDecryptOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let key = read_content(&self.key)?;
+        let mut writer = create_output(&self.output)?;
+        process_csv_decrypt(
+            &self.input,
+            &self.dialect.dialect(),
+            &self.columns,
+            &key,
+            &mut writer,
+        )?;
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+impl CmdExector for CsvPivotOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let opts = PivotOpts {
+            rows: self.rows,
+            cols: self.cols,
+            value: self.value,
+            agg: self.agg,
+        };
+        let pivot = process_csv_pivot(&self.input, &self.dialect.dialect(), &opts)?;
+        let mut writer = create_output(&self.output)?;
+        match self.format {
+            PivotFormat::Table => writer.write_all(pivot.to_table().as_bytes())?,
+            PivotFormat::Csv => pivot.write_csv(&mut writer)?,
+        }
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+impl CmdExector for CsvSubCommand {
+    async fn execute(self) -> anyhow::Result<()> {
+        match self {
+            CsvSubCommand::From(opts) => opts.execute().await,
+            CsvSubCommand::Stats(opts) => opts.execute().await,
+            CsvSubCommand::Join(opts) => opts.execute().await,
+            CsvSubCommand::Diff(opts) => opts.execute().await,
+            CsvSubCommand::Validate(opts) => opts.execute().await,
+            CsvSubCommand::Split(opts) => opts.execute().await,
+            CsvSubCommand::Encrypt(opts) => opts.execute().await,
+            CsvSubCommand::Decrypt(opts) => opts.execute().await,
+            CsvSubCommand::Pivot(opts) => opts.execute().await,
+        }
+    }
+}
+
 // impl TryFrom<&str> for OutputFormat {
 //     type Error = &'static str;
 
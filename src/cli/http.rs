@@ -1,6 +1,6 @@
 use std::path::PathBuf;
 
-use clap::{command, Parser};
+use clap::Parser;
 // use enum_dispatch::enum_dispatch;
 
 use crate::{process_http_serve, CmdExector};
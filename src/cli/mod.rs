@@ -25,6 +25,8 @@ pub struct Opts {
     pub cmd: SubCommand,
 }
 
+// parsed once per run, so the size of CsvOpts doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Parser)]
 #[enum_dispatch(CmdExector)]
 pub enum SubCommand {
@@ -51,6 +53,17 @@ fn verify_file(file_name: &str) -> Result<String, &'static str> {
     }
 }
 
+// an existing file or a glob pattern such as "exports/*.csv", expanded when the command runs
+fn verify_input(input: &str) -> Result<String, &'static str> {
+    if !input.contains(['*', '?', '[']) {
+        return verify_file(input);
+    }
+    match glob::Pattern::new(input) {
+        Ok(_) => Ok(input.into()),
+        Err(_) => Err("Invalid glob pattern"),
+    }
+}
+
 fn verify_path(path_name: &str) -> Result<PathBuf, &'static str> {
     let path = Path::new(path_name);
     if path.exists() && path.is_dir() {
@@ -75,11 +88,26 @@ fn verify_path(path_name: &str) -> Result<PathBuf, &'static str> {
 
 #[cfg(test)]
 mod tests {
-    use crate::cli::verify_file;
+    use clap::CommandFactory;
+
+    use crate::cli::{verify_file, verify_input, Opts};
+
+    #[test]
+    fn test_opts_debug_assert() {
+        Opts::command().debug_assert();
+    }
+
     #[test]
     fn test_verify_file() {
         assert_eq!(verify_file("-"), Ok("-".to_string()));
         assert_eq!(verify_file("Cargo.toml"), Ok("Cargo.toml".into()));
         assert_eq!(verify_file("not-exist"), Err("File does not exist"));
     }
+
+    #[test]
+    fn test_verify_input() {
+        assert_eq!(verify_input("assets/*.csv"), Ok("assets/*.csv".into()));
+        assert_eq!(verify_input("assets/[.csv"), Err("Invalid glob pattern"));
+        assert_eq!(verify_input("not-exist.csv"), Err("File does not exist"));
+    }
 }
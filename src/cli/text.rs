@@ -1,6 +1,6 @@
 use std::{fmt, path::PathBuf, str::FromStr};
 
-use clap::{command, Parser};
+use clap::Parser;
 // use enum_dispatch::enum_dispatch;
 
 use crate::{
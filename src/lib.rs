@@ -6,7 +6,7 @@ use enum_dispatch::enum_dispatch;
 
 pub use cli::*;
 pub use process::*;
-pub use util::read_content;
+pub use util::{create_output, read_content};
 
 #[allow(async_fn_in_trait)]
 #[enum_dispatch]
@@ -0,0 +1,187 @@
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+};
+
+use anyhow::anyhow;
+use csv::{StringRecord, WriterBuilder};
+
+use crate::process::encrypt_decrypt::ChaCha20Poly1305;
+
+use super::CsvDialect;
+
+// every non-empty cell of the columns gets its own nonce, so equal values do not
+// give equal ciphertexts, and is bound to its column name, so it does not decrypt
+// when moved to another column; "null" and "NULL" are data and encrypted too, only
+// empty cells stay empty; the other columns and the header are written as read
+pub fn process_csv_encrypt(
+    input: &str,
+    dialect: &CsvDialect,
+    columns: &[String],
+    key_base64: &str,
+    output: &mut dyn Write,
+) -> anyhow::Result<usize> {
+    rewrite_columns(input, dialect, columns, output, |column, value| {
+        ChaCha20Poly1305::seal(value, key_base64, column.as_bytes())
+    })
+}
+
+// base64, the form the key file is read in; only the owner can read the file and an
+// existing one is never replaced
+pub fn process_csv_generate_key(path: &str) -> anyhow::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(path).map_err(|e| match e.kind() {
+        io::ErrorKind::AlreadyExists => {
+            anyhow!("{} already exists, refusing to replace a key", path)
+        }
+        _ => e.into(),
+    })?;
+    file.write_all(ChaCha20Poly1305::generate_key().as_bytes())?;
+    Ok(())
+}
+
+pub fn process_csv_decrypt(
+    input: &str,
+    dialect: &CsvDialect,
+    columns: &[String],
+    key_base64: &str,
+    output: &mut dyn Write,
+) -> anyhow::Result<usize> {
+    rewrite_columns(input, dialect, columns, output, |column, value| {
+        ChaCha20Poly1305::open(value, key_base64, column.as_bytes())
+            .map_err(|e| anyhow!("can not decrypt {:?}: {}", value, e))
+    })
+}
+
+fn rewrite_columns(
+    input: &str,
+    dialect: &CsvDialect,
+    columns: &[String],
+    output: &mut dyn Write,
+    rewrite: impl Fn(&str, &str) -> anyhow::Result<String>,
+) -> anyhow::Result<usize> {
+    if columns.is_empty() {
+        return Err(anyhow!("at least one column is required"));
+    }
+    let mut reader = dialect.reader(input)?;
+    let header = dialect.headers(&mut reader)?;
+    let indices = columns
+        .iter()
+        .map(|column| {
+            header
+                .iter()
+                .position(|h| h == column)
+                .ok_or(anyhow!("column {} does not exist", column))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut writer = WriterBuilder::new()
+        .delimiter(dialect.delimiter)
+        .quote(dialect.quote)
+        .flexible(dialect.flexible)
+        .from_writer(output);
+    if dialect.has_headers {
+        writer.write_record(&header)?;
+    }
+    let mut rows = 0;
+    for result in reader.records() {
+        let record = result?;
+        let cells = record
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                if !indices.contains(&i) || value.is_empty() {
+                    return Ok(value.to_owned());
+                }
+                rewrite(&header[i], value).map_err(|e| {
+                    let line = record.position().map(|p| p.line()).unwrap_or_default();
+                    anyhow!("line {}, column {}: {}", line, &header[i], e)
+                })
+            })
+            .collect::<anyhow::Result<StringRecord>>()?;
+        writer.write_record(&cells)?;
+        rows += 1;
+    }
+    writer.flush()?;
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crypt(input: &str, key: &str, encrypt: bool, columns: &[&str]) -> anyhow::Result<Vec<u8>> {
+        let columns = columns.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        let mut content = Vec::new();
+        if encrypt {
+            process_csv_encrypt(input, &CsvDialect::default(), &columns, key, &mut content)?;
+        } else {
+            process_csv_decrypt(input, &CsvDialect::default(), &columns, key, &mut content)?;
+        }
+        Ok(content)
+    }
+
+    #[test]
+    fn test_process_csv_encrypt_decrypt() -> anyhow::Result<()> {
+        let key = ChaCha20Poly1305::generate_key();
+        let encrypted = crypt("assets/juventus.csv", &key, true, &["Name", "Nationality"])?;
+        let path = std::env::temp_dir().join("rcli_juventus_encrypted.csv");
+        std::fs::write(&path, &encrypted)?;
+
+        let mut reader = CsvDialect::default().reader_from(encrypted.as_slice());
+        let records = reader.records().collect::<Result<Vec<_>, _>>()?;
+        assert_ne!(&records[0][0], "Wojciech Szczesny");
+        assert_eq!(&records[0][1], "Goalkeeper");
+        // the same nationality, but every cell has its own nonce
+        assert_ne!(&records[1][3], &records[2][3]);
+
+        let path = path.to_string_lossy();
+        let decrypted = crypt(&path, &key, false, &["Name", "Nationality"])?;
+        assert_eq!(decrypted, std::fs::read("assets/juventus.csv")?);
+
+        let other = ChaCha20Poly1305::generate_key();
+        assert!(crypt(&path, &other, false, &["Name"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_encrypt_binds_column() -> anyhow::Result<()> {
+        let key = ChaCha20Poly1305::generate_key();
+        let input = std::env::temp_dir().join("rcli_nulls_plain.csv");
+        std::fs::write(&input, "a,b\nNULL,\nnull,x\n")?;
+        let encrypted = crypt(&input.to_string_lossy(), &key, true, &["a", "b"])?;
+        let mut reader = CsvDialect::default().reader_from(encrypted.as_slice());
+        let records = reader.records().collect::<Result<Vec<_>, _>>()?;
+        assert_ne!(&records[0][0], "NULL");
+        assert_ne!(&records[1][0], "null");
+        assert_eq!(&records[0][1], "");
+
+        // a ciphertext moved to another column does not decrypt
+        let swapped = std::env::temp_dir().join("rcli_nulls_swapped.csv");
+        let cells = [&records[1][1], &records[1][0]];
+        std::fs::write(&swapped, format!("a,b\n{},{}\n", cells[0], cells[1]))?;
+        assert!(crypt(&swapped.to_string_lossy(), &key, false, &["a", "b"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_generate_key() -> anyhow::Result<()> {
+        let path = std::env::temp_dir().join("rcli_generated.key");
+        let _ = std::fs::remove_file(&path);
+        let path = path.to_string_lossy();
+        process_csv_generate_key(&path)?;
+        assert!(process_csv_generate_key(&path).is_err());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(path.as_ref())?.permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use csv::StringRecord;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::{reader::fit_record, table::render_table, writer::Row, CsvDialect};
+
+#[derive(Debug, Default, Serialize)]
+pub struct CsvDiff {
+    pub added_columns: Vec<String>,
+    pub removed_columns: Vec<String>,
+    pub added: Vec<Row>,
+    pub removed: Vec<Row>,
+    pub changed: Vec<ChangedRow>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChangedRow {
+    pub key: Row,
+    pub changes: Vec<CellChange>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CellChange {
+    pub column: String,
+    pub old: String,
+    pub new: String,
+}
+
+// the old file is held in memory by key, the new one is streamed against it;
+// only columns present in both files are compared
+pub fn process_csv_diff(
+    old: &str,
+    new: &str,
+    dialect: &CsvDialect,
+    key: &[String],
+) -> anyhow::Result<CsvDiff> {
+    if key.is_empty() {
+        return Err(anyhow!("at least one key column is required"));
+    }
+    let mut reader = dialect.reader(old)?;
+    let mut old_header = dialect.headers(&mut reader)?;
+    let mut old_rows = Vec::new();
+    for result in reader.records() {
+        let mut record = result?;
+        fit_record(&mut old_header, &mut record);
+        old_rows.push(record);
+    }
+    let old_key = key_indices(&old_header, key, old)?;
+    let mut index = HashMap::new();
+    for (i, record) in old_rows.iter().enumerate() {
+        let k = key_of(record, &old_key);
+        if index.insert(k.clone(), i).is_some() {
+            return Err(anyhow!("duplicate key {:?} in {}", k.join(","), old));
+        }
+    }
+
+    let mut reader = dialect.reader(new)?;
+    let mut new_header = dialect.headers(&mut reader)?;
+    let new_key = key_indices(&new_header, key, new)?;
+    let mut diff = CsvDiff::default();
+    let mut seen = vec![false; old_rows.len()];
+    for result in reader.records() {
+        let mut record = result?;
+        fit_record(&mut new_header, &mut record);
+        let k = key_of(&record, &new_key);
+        let Some(&i) = index.get(&k) else {
+            diff.added.push(to_row(&new_header, &record));
+            continue;
+        };
+        if seen[i] {
+            return Err(anyhow!("duplicate key {:?} in {}", k.join(","), new));
+        }
+        seen[i] = true;
+        let changes = new_header
+            .iter()
+            .zip(record.iter())
+            .filter_map(|(column, value)| {
+                let j = old_header.iter().position(|h| h == column)?;
+                let before = old_rows[i].get(j).unwrap_or_default();
+                (before != value).then(|| CellChange {
+                    column: column.to_owned(),
+                    old: before.to_owned(),
+                    new: value.to_owned(),
+                })
+            })
+            .collect::<Vec<_>>();
+        if !changes.is_empty() {
+            let key = key
+                .iter()
+                .zip(k)
+                .map(|(column, value)| (column.clone(), Value::String(value)))
+                .collect();
+            diff.changed.push(ChangedRow { key, changes });
+        }
+    }
+    diff.removed = old_rows
+        .iter()
+        .zip(seen)
+        .filter(|(_, seen)| !*seen)
+        .map(|(record, _)| to_row(&old_header, record))
+        .collect();
+    diff.added_columns = columns_missing_from(&new_header, &old_header);
+    diff.removed_columns = columns_missing_from(&old_header, &new_header);
+    Ok(diff)
+}
+
+fn key_indices(header: &StringRecord, key: &[String], input: &str) -> anyhow::Result<Vec<usize>> {
+    key.iter()
+        .map(|column| {
+            header.iter().position(|h| h == column).ok_or(anyhow!(
+                "column {} does not exist in {}",
+                column,
+                input
+            ))
+        })
+        .collect()
+}
+
+fn key_of(record: &StringRecord, indices: &[usize]) -> Vec<String> {
+    indices
+        .iter()
+        .map(|&i| record.get(i).unwrap_or_default().to_owned())
+        .collect()
+}
+
+fn to_row(header: &StringRecord, record: &StringRecord) -> Row {
+    header
+        .iter()
+        .zip(record.iter())
+        .map(|(h, v)| (h.to_owned(), Value::String(v.to_owned())))
+        .collect()
+}
+
+fn columns_missing_from(header: &StringRecord, other: &StringRecord) -> Vec<String> {
+    header
+        .iter()
+        .filter(|h| !other.iter().any(|o| o == *h))
+        .map(|h| h.to_owned())
+        .collect()
+}
+
+impl CsvDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+            && self.added_columns.is_empty()
+            && self.removed_columns.is_empty()
+    }
+
+    // a summary line, then one table per kind of change
+    pub fn to_table(&self) -> String {
+        let mut report = format!(
+            "{} added, {} removed, {} changed\n",
+            self.added.len(),
+            self.removed.len(),
+            self.changed.len()
+        );
+        if !self.added_columns.is_empty() {
+            report.push_str(&format!(
+                "added columns: {}\n",
+                self.added_columns.join(", ")
+            ));
+        }
+        if !self.removed_columns.is_empty() {
+            report.push_str(&format!(
+                "removed columns: {}\n",
+                self.removed_columns.join(", ")
+            ));
+        }
+        for (title, rows) in [("Added", &self.added), ("Removed", &self.removed)] {
+            if rows.is_empty() {
+                continue;
+            }
+            // flexible rows read later may have widened the header
+            let mut header = Vec::<String>::new();
+            for key in rows.iter().flat_map(|row| row.keys()) {
+                if !header.contains(key) {
+                    header.push(key.clone());
+                }
+            }
+            let rows = rows
+                .iter()
+                .map(|row| {
+                    header
+                        .iter()
+                        .map(|h| row.get(h).map(cell).unwrap_or_default())
+                        .collect()
+                })
+                .collect::<Vec<_>>();
+            report.push_str(&format!("\n{}:\n", title));
+            report.push_str(&render_table(&header, &rows, 30, false));
+        }
+        if let Some(first) = self.changed.first() {
+            let mut header = first.key.keys().cloned().collect::<Vec<_>>();
+            header.extend(["Column", "Old", "New"].map(String::from));
+            let rows = self
+                .changed
+                .iter()
+                .flat_map(|row| {
+                    row.changes.iter().map(|change| {
+                        let mut cells = row.key.values().map(cell).collect::<Vec<_>>();
+                        cells.extend([
+                            change.column.clone(),
+                            change.old.clone(),
+                            change.new.clone(),
+                        ]);
+                        cells
+                    })
+                })
+                .collect::<Vec<_>>();
+            report.push_str("\nChanged:\n");
+            report.push_str(&render_table(&header, &rows, 30, false));
+        }
+        report
+    }
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_csv_diff() -> anyhow::Result<()> {
+        let diff = process_csv_diff(
+            "fixtures/roster_old.csv",
+            "fixtures/roster_new.csv",
+            &CsvDialect::default(),
+            &["Name".into()],
+        )?;
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0]["Name"], "Matthijs de Ligt");
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0]["Name"], "Gianluigi Buffon");
+        assert_eq!(diff.changed.len(), 1);
+        let changed = &diff.changed[0];
+        assert_eq!(changed.key["Name"], "Paulo Dybala");
+        let columns = changed
+            .changes
+            .iter()
+            .map(|c| (c.column.as_str(), c.old.as_str(), c.new.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            columns,
+            vec![
+                ("Position", "Second Striker", "Centre-Forward"),
+                ("Kit Number", "10", "21")
+            ]
+        );
+        let report = diff.to_table();
+        assert!(report.starts_with("1 added, 1 removed, 1 changed\n"));
+        assert!(report.contains("| Paulo Dybala | Kit Number | 10             | 21             |"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_diff_flexible_rows() -> anyhow::Result<()> {
+        let dir = std::env::temp_dir();
+        let old = dir.join("rcli_diff_flexible_old.csv");
+        let new = dir.join("rcli_diff_flexible_new.csv");
+        std::fs::write(&old, "a,b\n1,x\n")?;
+        std::fs::write(&new, "a,b\n2,y\n3,z,extra\n")?;
+        let dialect = CsvDialect {
+            flexible: true,
+            ..Default::default()
+        };
+        let diff = process_csv_diff(
+            old.to_str().unwrap(),
+            new.to_str().unwrap(),
+            &dialect,
+            &["a".into()],
+        )?;
+        assert_eq!(diff.added.len(), 2);
+        let report = diff.to_table();
+        assert!(report.contains("| a | b | col_3 |"), "{}", report);
+        assert!(report.contains("| 2 | y |       |"), "{}", report);
+        assert!(report.contains("| 3 | z | extra |"), "{}", report);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_diff_same_file() -> anyhow::Result<()> {
+        let diff = process_csv_diff(
+            "assets/juventus.csv",
+            "assets/juventus.csv",
+            &CsvDialect::default(),
+            &["Name".into()],
+        )?;
+        assert!(diff.is_empty());
+        Ok(())
+    }
+}
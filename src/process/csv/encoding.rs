@@ -0,0 +1,208 @@
+use std::{
+    io::{self, Cursor, Read, Write},
+    str,
+};
+
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+
+// bytes read ahead to guess the encoding of input without a BOM
+const DETECT_SAMPLE_BYTES: usize = 64 * 1024;
+
+// input is transcoded to utf-8 before parsing: a BOM always wins, then the given
+// encoding, then a guess from the start of the input
+pub fn decode_reader(
+    mut rdr: Box<dyn Read>,
+    encoding: Option<&'static Encoding>,
+) -> io::Result<Box<dyn Read>> {
+    let mut sample = Vec::new();
+    (&mut rdr)
+        .take(DETECT_SAMPLE_BYTES as u64)
+        .read_to_end(&mut sample)?;
+    let bom = Encoding::for_bom(&sample).is_some();
+    let encoding = encoding.unwrap_or_else(|| detect(&sample, sample.len() < DETECT_SAMPLE_BYTES));
+    let rdr = Cursor::new(sample).chain(rdr);
+    if encoding == UTF_8 && !bom {
+        // left as it is, so invalid bytes are still reported by the csv reader
+        return Ok(Box::new(rdr));
+    }
+    Ok(Box::new(
+        DecodeReaderBytesBuilder::new()
+            .encoding(Some(encoding))
+            .bom_override(true)
+            .strip_bom(true)
+            .build(rdr),
+    ))
+}
+
+// utf-16 without a BOM can not be told apart reliably and has to be given explicitly
+pub fn detect(sample: &[u8], last: bool) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(sample) {
+        return encoding;
+    }
+    if looks_like_utf8(sample, last) {
+        return UTF_8;
+    }
+    let mut detector = EncodingDetector::new();
+    detector.feed(sample, last);
+    detector.guess(None, false)
+}
+
+// any invalid byte rules utf-8 out, except for a character cut off at the end of the sample
+fn looks_like_utf8(sample: &[u8], last: bool) -> bool {
+    match str::from_utf8(sample) {
+        Ok(_) => true,
+        Err(e) => !last && e.error_len().is_none(),
+    }
+}
+
+// re-encodes the utf-8 written to it, utf-16 output starts with a BOM;
+// characters the encoding has no mapping for are an error instead of being replaced
+pub struct EncodeWriter<W: Write> {
+    w: W,
+    encoding: &'static Encoding,
+    // the start of a character split across writes
+    pending: Vec<u8>,
+    started: bool,
+}
+
+impl<W: Write> EncodeWriter<W> {
+    pub fn new(w: W, encoding: &'static Encoding) -> Self {
+        Self {
+            w,
+            encoding,
+            pending: Vec::new(),
+            started: false,
+        }
+    }
+
+    fn encode(&mut self, s: &str) -> io::Result<()> {
+        if self.encoding == UTF_16LE || self.encoding == UTF_16BE {
+            let le = self.encoding == UTF_16LE;
+            let bom = (!self.started).then_some('\u{feff}');
+            let bytes = bom
+                .into_iter()
+                .chain(s.chars())
+                .flat_map(|c| c.encode_utf16(&mut [0; 2]).to_vec())
+                .flat_map(|u| if le { u.to_le_bytes() } else { u.to_be_bytes() })
+                .collect::<Vec<_>>();
+            self.started = true;
+            return self.w.write_all(&bytes);
+        }
+        let (bytes, _, unmappable) = self.encoding.encode(s);
+        if unmappable {
+            let c = s
+                .chars()
+                .find(|c| self.encoding.encode(&c.to_string()).2)
+                .unwrap_or_default();
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{:?} can not be encoded as {}", c, self.encoding.name()),
+            ));
+        }
+        self.started = true;
+        self.w.write_all(&bytes)
+    }
+}
+
+impl<W: Write> Write for EncodeWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        let valid = match str::from_utf8(&self.pending) {
+            Ok(s) => s.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+        let rest = self.pending.split_off(valid);
+        let complete = std::mem::replace(&mut self.pending, rest);
+        let s = str::from_utf8(&complete).expect("checked above");
+        self.encode(s)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::{GBK, WINDOWS_1252};
+
+    fn decode(bytes: Vec<u8>, encoding: Option<&'static Encoding>) -> io::Result<String> {
+        let mut decoded = String::new();
+        decode_reader(Box::new(Cursor::new(bytes)), encoding)?.read_to_string(&mut decoded)?;
+        Ok(decoded)
+    }
+
+    #[test]
+    fn test_decode_reader() -> io::Result<()> {
+        let text = "名字,国籍\n布冯,意大利\n";
+        let gbk = GBK.encode(text).0.into_owned();
+        assert_eq!(decode(gbk.clone(), None)?, text);
+        assert_eq!(decode(gbk, Some(GBK))?, text);
+
+        let text = "Name,Club\nGonzalo Higuaín,Juventus\nNicolò Barella,Inter\n";
+        let latin = WINDOWS_1252.encode(text).0.into_owned();
+        assert_eq!(decode(latin, None)?, text);
+
+        let mut utf16 = vec![0xff, 0xfe];
+        utf16.extend(text.encode_utf16().flat_map(|u| u.to_le_bytes()));
+        // the BOM wins over a wrong explicit encoding
+        assert_eq!(decode(utf16, Some(GBK))?, text);
+
+        let bom = [b"\xef\xbb\xbf".as_slice(), text.as_bytes()].concat();
+        assert_eq!(decode(bom, None)?, text);
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect_utf8() {
+        let text = "Name,Club\nGonzalo Higuaín,Juventus\n".as_bytes();
+        assert_eq!(detect(text, true), UTF_8);
+        // the sample ends in the middle of "í"
+        let cut = &text[..text.len() - 12];
+        assert_eq!(detect(cut, false), UTF_8);
+        assert_ne!(detect(cut, true), UTF_8);
+        assert_eq!(detect(&GBK.encode("名字,国籍\n").0, true), GBK);
+    }
+
+    #[test]
+    fn test_decode_reader_windows_1252_beyond_sample() -> io::Result<()> {
+        let mut text = String::from("Name,Club,Notes\n");
+        let mut row = 0;
+        while text.len() <= DETECT_SAMPLE_BYTES {
+            text += &format!(
+                "Gonzalo Higuaín {row},Juventus,{}\n",
+                "scored a brace in the derby ".repeat(10)
+            );
+            row += 1;
+        }
+        let latin = WINDOWS_1252.encode(&text).0.into_owned();
+        assert!(latin.len() > DETECT_SAMPLE_BYTES);
+        assert_eq!(decode(latin, None)?, text);
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_writer() -> io::Result<()> {
+        let text = "Gonzalo Higuaín";
+        let mut w = EncodeWriter::new(Vec::new(), WINDOWS_1252);
+        // split inside "í"
+        let (a, b) = text.as_bytes().split_at(14);
+        w.write_all(a)?;
+        w.write_all(b)?;
+        assert_eq!(w.w, WINDOWS_1252.encode(text).0.as_ref());
+
+        let mut w = EncodeWriter::new(Vec::new(), UTF_16LE);
+        w.write_all(b"ab")?;
+        w.write_all(b"c")?;
+        assert_eq!(w.w, b"\xff\xfea\0b\0c\0");
+
+        let mut w = EncodeWriter::new(Vec::new(), WINDOWS_1252);
+        assert!(w.write_all("布冯".as_bytes()).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,249 @@
+use std::{cmp::Ordering, str::FromStr};
+
+use anyhow::anyhow;
+use csv::StringRecord;
+use regex::Regex;
+
+use super::infer::is_null;
+
+#[derive(Debug, Clone)]
+pub enum Condition {
+    Eq(String),
+    Ne(String),
+    Lt(String),
+    Le(String),
+    Gt(String),
+    Ge(String),
+    Match(Regex),
+    NotMatch(Regex),
+}
+
+// Position == "Goalkeeper", "Kit Number" >= 10, Name =~ "^G"
+#[derive(Debug, Clone)]
+pub struct Predicate {
+    pub column: String,
+    pub condition: Condition,
+}
+
+// operators sharing a prefix are listed longest first
+const OPERATORS: [&str; 8] = ["==", "!=", "<=", ">=", "=~", "!~", "<", ">"];
+
+impl FromStr for Predicate {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (column, rest) = match unquote_prefix(s) {
+            Some((column, rest)) => (column.to_owned(), rest.trim_start()),
+            None => {
+                let end = s
+                    .find(['=', '!', '<', '>'])
+                    .ok_or("Must be <column> <op> <value>")?;
+                (s[..end].trim().to_owned(), &s[end..])
+            }
+        };
+        if column.is_empty() {
+            return Err("Column name can not be empty");
+        }
+        let op = OPERATORS
+            .iter()
+            .find(|op| rest.starts_with(*op))
+            .ok_or("Operator must be one of ==, !=, <, <=, >, >=, =~, !~")?;
+        let value = rest[op.len()..].trim();
+        let value = match unquote_prefix(value) {
+            Some((value, "")) => value,
+            _ => value,
+        }
+        .to_owned();
+        let condition = match *op {
+            "==" => Condition::Eq(value),
+            "!=" => Condition::Ne(value),
+            "<" => Condition::Lt(value),
+            "<=" => Condition::Le(value),
+            ">" => Condition::Gt(value),
+            ">=" => Condition::Ge(value),
+            "=~" => Condition::Match(Regex::new(&value).map_err(|_| "Invalid regex")?),
+            _ => Condition::NotMatch(Regex::new(&value).map_err(|_| "Invalid regex")?),
+        };
+        Ok(Self { column, condition })
+    }
+}
+
+impl Condition {
+    pub fn matches(&self, value: &str) -> bool {
+        match self {
+            Condition::Eq(v) => compare_values(value, v) == Ordering::Equal,
+            Condition::Ne(v) => compare_values(value, v) != Ordering::Equal,
+            Condition::Lt(v) => compare_values(value, v) == Ordering::Less,
+            Condition::Le(v) => compare_values(value, v) != Ordering::Greater,
+            Condition::Gt(v) => compare_values(value, v) == Ordering::Greater,
+            Condition::Ge(v) => compare_values(value, v) != Ordering::Less,
+            Condition::Match(re) => re.is_match(value),
+            Condition::NotMatch(re) => !re.is_match(value),
+        }
+    }
+}
+
+// "Kit Number:desc", the direction defaults to ascending
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortKey {
+    pub column: String,
+    pub descending: bool,
+}
+
+impl FromStr for SortKey {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (column, descending) = match s.rsplit_once(':') {
+            Some((column, "asc")) => (column, false),
+            Some((column, "desc")) => (column, true),
+            _ => (s, false),
+        };
+        if column.is_empty() {
+            return Err("Column name can not be empty");
+        }
+        Ok(Self {
+            column: column.to_owned(),
+            descending,
+        })
+    }
+}
+
+// `"Kit Number" >= 10` -> ("Kit Number", " >= 10")
+fn unquote_prefix(s: &str) -> Option<(&str, &str)> {
+    let quote = s.chars().next().filter(|c| matches!(c, '"' | '\''))?;
+    let end = s[1..].find(quote)? + 1;
+    Some((&s[1..end], &s[end + 1..]))
+}
+
+// numbers compare as numbers so "9" < "10", anything else compares as text
+pub fn compare_values(a: &str, b: &str) -> Ordering {
+    match (as_number(a), as_number(b)) {
+        (Some(a), Some(b)) => a.total_cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a.cmp(b),
+    }
+}
+
+fn as_number(value: &str) -> Option<f64> {
+    value.trim().parse::<f64>().ok().filter(|f| f.is_finite())
+}
+
+// --where, --sort-by and --limit resolved against the header
+#[derive(Debug, Default)]
+pub struct RowQuery {
+    predicates: Vec<(usize, Condition)>,
+    sort: Vec<(usize, bool)>,
+    pub limit: Option<usize>,
+}
+
+impl RowQuery {
+    pub fn new(
+        header: &StringRecord,
+        predicates: &[Predicate],
+        sort_by: &[SortKey],
+        limit: Option<usize>,
+    ) -> anyhow::Result<Self> {
+        let position = |column: &str| {
+            header
+                .iter()
+                .position(|h| h == column)
+                .ok_or(anyhow!("column {} does not exist", column))
+        };
+        let predicates = predicates
+            .iter()
+            .map(|p| Ok((position(&p.column)?, p.condition.clone())))
+            .collect::<anyhow::Result<_>>()?;
+        let sort = sort_by
+            .iter()
+            .map(|k| Ok((position(&k.column)?, k.descending)))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            predicates,
+            sort,
+            limit,
+        })
+    }
+
+    // every predicate has to hold
+    pub fn matches(&self, record: &StringRecord) -> bool {
+        self.predicates
+            .iter()
+            .all(|(i, condition)| condition.matches(record.get(*i).unwrap_or_default()))
+    }
+
+    // sorting needs every matching row before the first one can be written
+    pub fn is_sorted(&self) -> bool {
+        !self.sort.is_empty()
+    }
+
+    // stable, so rows with equal keys keep their input order; empty cells always go last
+    pub fn sort(&self, records: &mut [StringRecord]) {
+        records.sort_by(|a, b| {
+            self.sort
+                .iter()
+                .fold(Ordering::Equal, |ordering, &(i, descending)| {
+                    ordering.then_with(|| {
+                        let a = a.get(i).unwrap_or_default();
+                        let b = b.get(i).unwrap_or_default();
+                        match (is_null(a), is_null(b)) {
+                            (true, true) => Ordering::Equal,
+                            (true, false) => Ordering::Greater,
+                            (false, true) => Ordering::Less,
+                            _ if descending => compare_values(b, a),
+                            _ => compare_values(a, b),
+                        }
+                    })
+                })
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_predicate() -> anyhow::Result<()> {
+        let p = r#"Position == "Goalkeeper""#;
+        let p = p.parse::<Predicate>().map_err(anyhow::Error::msg)?;
+        assert_eq!(p.column, "Position");
+        assert!(p.condition.matches("Goalkeeper"));
+        assert!(!p.condition.matches("Centre-Back"));
+
+        let p = r#""Kit Number" >= 10"#.parse::<Predicate>().map_err(anyhow::Error::msg)?;
+        assert_eq!(p.column, "Kit Number");
+        assert!(p.condition.matches("10"));
+        assert!(p.condition.matches("21"));
+        assert!(!p.condition.matches("9"));
+
+        let p = "Name =~ '^G'"
+            .parse::<Predicate>()
+            .map_err(anyhow::Error::msg)?;
+        assert!(p.condition.matches("Gonzalo Higuaín"));
+        assert!(!p.condition.matches("Paulo Dybala"));
+
+        assert!("Name".parse::<Predicate>().is_err());
+        assert!("Name =~ '('".parse::<Predicate>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_row_query_sort() -> anyhow::Result<()> {
+        let header = StringRecord::from(vec!["name", "no"]);
+        let sort_by = vec!["no:desc".parse::<SortKey>().map_err(anyhow::Error::msg)?];
+        let query = RowQuery::new(&header, &[], &sort_by, None)?;
+        let mut records = vec![
+            StringRecord::from(vec!["a", "9"]),
+            StringRecord::from(vec!["b", ""]),
+            StringRecord::from(vec!["c", "10"]),
+        ];
+        query.sort(&mut records);
+        let names = records.iter().map(|r| &r[0]).collect::<Vec<_>>();
+        assert_eq!(names, vec!["c", "a", "b"]);
+
+        let sort_by = vec!["age".parse::<SortKey>().map_err(anyhow::Error::msg)?];
+        assert!(RowQuery::new(&header, &[], &sort_by, None).is_err());
+        Ok(())
+    }
+}
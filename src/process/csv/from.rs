@@ -0,0 +1,148 @@
+use std::{collections::HashSet, io::Write};
+
+use anyhow::anyhow;
+use csv::WriterBuilder;
+use serde_json::{Map, Value};
+
+use crate::{cli::OutputFormat, util::read_text};
+
+// the header is the union of all keys, so every document is loaded before writing
+pub fn process_csv_from(
+    input: &str,
+    format: OutputFormat,
+    delimiter: u8,
+    output: &mut dyn Write,
+) -> anyhow::Result<usize> {
+    let content = read_text(input)?;
+    let docs: Vec<Value> = match format {
+        OutputFormat::Json => serde_json::from_str(&content)?,
+        OutputFormat::Yaml => serde_yaml::from_str(&content)?,
+        OutputFormat::Ndjson => content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?,
+        _ => return Err(anyhow!("Unsupported format")),
+    };
+
+    let mut header = Vec::new();
+    let mut seen = HashSet::new();
+    let mut rows = Vec::with_capacity(docs.len());
+    for (i, doc) in docs.into_iter().enumerate() {
+        let Value::Object(object) = doc else {
+            return Err(anyhow!("record {} is not an object", i + 1));
+        };
+        let mut row = Map::new();
+        flatten("", object, &mut row);
+        for key in row.keys() {
+            if seen.insert(key.clone()) {
+                header.push(key.clone());
+            }
+        }
+        rows.push(row);
+    }
+
+    let mut writer = WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(output);
+    writer.write_record(&header)?;
+    for row in rows.iter() {
+        writer.write_record(header.iter().map(|h| cell(row.get(h))))?;
+    }
+    writer.flush()?;
+    Ok(rows.len())
+}
+
+// {"address": {"city": "Turin"}} -> {"address.city": "Turin"}
+fn flatten(prefix: &str, object: Map<String, Value>, row: &mut Map<String, Value>) {
+    for (key, value) in object {
+        let key = if prefix.is_empty() {
+            key
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        match value {
+            Value::Object(object) => flatten(&key, object, row),
+            value => {
+                row.insert(key, value);
+            }
+        }
+    }
+}
+
+// arrays have no column of their own, they are kept as a json string
+fn cell(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(value) => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{process_csv, CsvConvertOpts, CsvDialect};
+
+    #[test]
+    fn test_process_csv_from_round_trip() -> anyhow::Result<()> {
+        for format in [OutputFormat::Json, OutputFormat::Yaml, OutputFormat::Ndjson] {
+            let opts = CsvConvertOpts {
+                format,
+                ..Default::default()
+            };
+            let mut converted = Vec::new();
+            process_csv(
+                &["assets/juventus.csv".to_owned()],
+                &CsvDialect::default(),
+                &opts,
+                &mut converted,
+            )?;
+            let path = std::env::temp_dir().join(format!("rcli_round_trip.{}", format));
+            std::fs::write(&path, converted)?;
+
+            let mut csv = Vec::new();
+            let rows = process_csv_from(path.to_str().unwrap(), format, b',', &mut csv)?;
+            assert_eq!(rows, 27);
+            assert_eq!(
+                String::from_utf8(csv)?,
+                std::fs::read_to_string("assets/juventus.csv")?
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_from_nested() -> anyhow::Result<()> {
+        let mut csv = Vec::new();
+        process_csv_from("fixtures/nested.json", OutputFormat::Json, b',', &mut csv)?;
+        let csv = String::from_utf8(csv)?;
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "name,address.city,address.geo.lat,tags,age");
+        assert_eq!(lines[1], r#"Paulo Dybala,Turin,45.07,"[""fw"",""10""]","#);
+        assert_eq!(lines[2], "Leonardo Bonucci,,,,32");
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_from_reads_input_as_is() -> anyhow::Result<()> {
+        let path = std::env::temp_dir().join("rcli_from_block.yaml");
+        std::fs::write(&path, "- name: Dybala\n  note: |\n    left foot\n")?;
+        let mut csv = Vec::new();
+        process_csv_from(path.to_str().unwrap(), OutputFormat::Yaml, b',', &mut csv)?;
+        assert_eq!(
+            String::from_utf8(csv)?,
+            "name,note\nDybala,\"left foot\n\"\n"
+        );
+
+        std::fs::write(&path, b"[{\"name\": \"Higua\xedn\"}]")?;
+        let result = process_csv_from(
+            path.to_str().unwrap(),
+            OutputFormat::Json,
+            b',',
+            &mut Vec::new(),
+        );
+        assert!(result.is_err());
+        Ok(())
+    }
+}
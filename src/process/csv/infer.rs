@@ -0,0 +1,235 @@
+use anyhow::anyhow;
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use csv::StringRecord;
+use serde_json::Value;
+
+use crate::cli::ColumnType;
+
+pub fn is_null(value: &str) -> bool {
+    matches!(value, "" | "null" | "NULL" | "Null")
+}
+
+// the narrowest type of a single non-null cell
+fn cell_type(value: &str) -> ColumnType {
+    if has_leading_zero(value) {
+        ColumnType::String
+    } else if is_int(value) {
+        ColumnType::Int
+    } else if is_float(value) {
+        ColumnType::Float
+    } else if parse_bool(value).is_some() {
+        ColumnType::Bool
+    } else if NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok() {
+        ColumnType::Date
+    } else if parse_datetime(value).is_some() {
+        ColumnType::DateTime
+    } else {
+        ColumnType::String
+    }
+}
+
+fn merge(a: ColumnType, b: ColumnType) -> ColumnType {
+    use ColumnType::*;
+    match (a, b) {
+        (a, b) if a == b => a,
+        (Int, Float) | (Float, Int) => Float,
+        (Date, DateTime) | (DateTime, Date) => DateTime,
+        _ => String,
+    }
+}
+
+// running per column type, so a whole file can be scanned in constant memory
+#[derive(Debug, Default)]
+pub struct TypeInference {
+    seen: Vec<Option<ColumnType>>,
+}
+
+impl TypeInference {
+    pub fn update(&mut self, record: &StringRecord) {
+        if self.seen.len() < record.len() {
+            self.seen.resize(record.len(), None);
+        }
+        for (seen, value) in self.seen.iter_mut().zip(record.iter()) {
+            if is_null(value) {
+                continue;
+            }
+            let ty = cell_type(value);
+            *seen = Some(seen.map_or(ty, |s| merge(s, ty)));
+        }
+    }
+
+    // None keeps the raw string, which is what every column gets with inference turned off
+    pub fn types(
+        &self,
+        header: &StringRecord,
+        infer: bool,
+        overrides: &[(String, ColumnType)],
+    ) -> anyhow::Result<Vec<Option<ColumnType>>> {
+        let mut types = (0..header.len())
+            .map(|i| {
+                infer.then(|| {
+                    self.seen
+                        .get(i)
+                        .copied()
+                        .flatten()
+                        // a column whose cells are all null is treated as string
+                        .unwrap_or(ColumnType::String)
+                })
+            })
+            .collect::<Vec<_>>();
+        for (name, ty) in overrides {
+            let i = header
+                .iter()
+                .position(|h| h == name)
+                .ok_or(anyhow!("column {} does not exist", name))?;
+            types[i] = Some(*ty);
+        }
+        Ok(types)
+    }
+}
+
+// "null" in a string column is text, only an empty cell is null there
+pub fn to_value(value: &str, ty: ColumnType) -> anyhow::Result<Value> {
+    let null = match ty {
+        ColumnType::String => value.is_empty(),
+        _ => is_null(value),
+    };
+    if null {
+        return Ok(Value::Null);
+    }
+    let parsed = match ty {
+        ColumnType::String => Some(Value::String(value.to_owned())),
+        // dates stay strings, but have to be valid ones
+        ColumnType::Date | ColumnType::DateTime => {
+            is_type(value, ty).then(|| Value::String(value.to_owned()))
+        }
+        ColumnType::Int => value.parse::<i64>().ok().map(Value::from),
+        ColumnType::Float => value
+            .parse::<f64>()
+            .ok()
+            .filter(|f| f.is_finite())
+            .map(Value::from),
+        ColumnType::Bool => parse_bool(value).map(Value::Bool),
+    };
+    parsed.ok_or(anyhow!("can not parse {:?} as {}", value, ty))
+}
+
+// whether a non-null cell can be read as the given type
+pub fn is_type(value: &str, ty: ColumnType) -> bool {
+    match ty {
+        ColumnType::String => true,
+        ColumnType::Int => is_int(value),
+        ColumnType::Float => is_int(value) || is_float(value),
+        ColumnType::Bool => parse_bool(value).is_some(),
+        ColumnType::Date => NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok(),
+        ColumnType::DateTime => parse_datetime(value).is_some(),
+    }
+}
+
+// "007" is kept as a string, leading zeros usually mean an identifier
+fn has_leading_zero(value: &str) -> bool {
+    let digits = value.strip_prefix('-').unwrap_or(value);
+    digits.len() > 1 && digits.starts_with('0') && !digits.starts_with("0.")
+}
+
+fn is_int(value: &str) -> bool {
+    value.parse::<i64>().is_ok()
+}
+
+// f64 also parses "inf" and "NaN", which we don't want to treat as numbers
+fn is_float(value: &str) -> bool {
+    value
+        .chars()
+        .all(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+        && value.contains(|c: char| c.is_ascii_digit())
+        && value.parse::<f64>().is_ok()
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_datetime(value: &str) -> Option<NaiveDateTime> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.naive_utc())
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S"))
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S"))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn infer_type<'a>(values: impl Iterator<Item = &'a str>) -> ColumnType {
+        let mut inference = TypeInference::default();
+        for value in values {
+            inference.update(&StringRecord::from(vec![value]));
+        }
+        let header = StringRecord::from(vec!["a"]);
+        inference.types(&header, true, &[]).unwrap()[0].unwrap()
+    }
+
+    #[test]
+    fn test_infer_type() {
+        assert_eq!(infer_type(["1", "37", ""].into_iter()), ColumnType::Int);
+        assert_eq!(infer_type(["1", "2.5"].into_iter()), ColumnType::Float);
+        assert_eq!(infer_type(["true", "False"].into_iter()), ColumnType::Bool);
+        assert_eq!(infer_type(["2024-01-31"].into_iter()), ColumnType::Date);
+        assert_eq!(
+            infer_type(["2024-01-31", "2024-01-31T10:00:00Z"].into_iter()),
+            ColumnType::DateTime
+        );
+        assert_eq!(infer_type(["007", "12"].into_iter()), ColumnType::String);
+        assert_eq!(infer_type(["0.5", "0"].into_iter()), ColumnType::Float);
+        assert_eq!(infer_type(["1", "NaN"].into_iter()), ColumnType::String);
+        assert_eq!(infer_type(["", "null"].into_iter()), ColumnType::String);
+    }
+
+    #[test]
+    fn test_type_inference() -> anyhow::Result<()> {
+        let header = StringRecord::from(vec!["id", "score", "note"]);
+        let mut inference = TypeInference::default();
+        inference.update(&StringRecord::from(vec!["1", "", "a"]));
+        inference.update(&StringRecord::from(vec!["2", "3.5", "b"]));
+        let types = inference.types(&header, true, &[("note".into(), ColumnType::Int)])?;
+        assert_eq!(
+            types,
+            vec![
+                Some(ColumnType::Int),
+                Some(ColumnType::Float),
+                Some(ColumnType::Int)
+            ]
+        );
+        let types = inference.types(&header, false, &[])?;
+        assert_eq!(types, vec![None, None, None]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_value() -> anyhow::Result<()> {
+        assert_eq!(to_value("37", ColumnType::Int)?, Value::from(37));
+        assert_eq!(to_value("", ColumnType::Int)?, Value::Null);
+        assert_eq!(to_value("37", ColumnType::String)?, Value::from("37"));
+        assert!(to_value("abc", ColumnType::Int).is_err());
+        assert_eq!(to_value("NULL", ColumnType::Int)?, Value::Null);
+        assert_eq!(to_value("null", ColumnType::String)?, Value::from("null"));
+        assert_eq!(to_value("", ColumnType::String)?, Value::Null);
+        assert_eq!(
+            to_value("2024-01-31", ColumnType::Date)?,
+            Value::from("2024-01-31")
+        );
+        assert!(to_value("31/01/2024", ColumnType::Date).is_err());
+        assert!(to_value("2024-02-30", ColumnType::Date).is_err());
+        assert_eq!(
+            to_value("2024-01-31T10:00:00Z", ColumnType::DateTime)?,
+            Value::from("2024-01-31T10:00:00Z")
+        );
+        assert!(to_value("yesterday", ColumnType::DateTime).is_err());
+        Ok(())
+    }
+}
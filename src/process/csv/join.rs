@@ -0,0 +1,243 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+};
+
+use anyhow::anyhow;
+use csv::StringRecord;
+
+use crate::cli::JoinKind;
+
+use super::{
+    convert_records, infer::is_null, reader::fit_record, CsvConvertOpts, CsvDialect, CsvOutput,
+};
+
+#[derive(Debug, Clone)]
+pub struct JoinOpts {
+    // key columns, present under the same name in both files
+    pub on: Vec<String>,
+    pub kind: JoinKind,
+    // non-key columns that exist in both files are renamed with these prefixes
+    pub left_prefix: String,
+    pub right_prefix: String,
+}
+
+impl Default for JoinOpts {
+    fn default() -> Self {
+        Self {
+            on: Vec::new(),
+            kind: JoinKind::Inner,
+            left_prefix: "left_".into(),
+            right_prefix: "right_".into(),
+        }
+    }
+}
+
+// the right file is indexed in memory, the left one is read in order,
+// so the output follows the left file with unmatched right rows last
+pub fn process_csv_join(
+    left: &str,
+    right: &str,
+    dialect: &CsvDialect,
+    join: &JoinOpts,
+    opts: &CsvConvertOpts,
+    output: &mut dyn Write,
+) -> anyhow::Result<CsvOutput> {
+    if join.on.is_empty() {
+        return Err(anyhow!("at least one key column is required"));
+    }
+    let mut reader = dialect.reader(right)?;
+    let mut right_header = dialect.headers(&mut reader)?;
+    let mut right_rows = Vec::new();
+    for result in reader.records() {
+        let mut record = result?;
+        fit_record(&mut right_header, &mut record);
+        right_rows.push(record);
+    }
+    let right_keys = key_indices(&right_header, &join.on, right)?;
+    let mut index: HashMap<Vec<&str>, Vec<usize>> = HashMap::new();
+    for (i, record) in right_rows.iter().enumerate() {
+        if let Some(key) = key(record, &right_keys) {
+            index.entry(key).or_default().push(i);
+        }
+    }
+    let right_columns = (0..right_header.len())
+        .filter(|i| !right_keys.contains(i))
+        .collect::<Vec<_>>();
+
+    let mut reader = dialect.reader(left)?;
+    let left_header = dialect.headers(&mut reader)?;
+    let left_keys = key_indices(&left_header, &join.on, left)?;
+    let header = joined_header(&left_header, &right_header, &right_columns, join)?;
+
+    let mut matched = vec![false; right_rows.len()];
+    let mut records = Vec::new();
+    for result in reader.records() {
+        let record = result?;
+        // extra cells of flexible rows have no column in the joined header
+        let cells = (0..left_header.len())
+            .map(|i| record.get(i).unwrap_or_default())
+            .collect::<Vec<_>>();
+        let matches = key(&record, &left_keys).and_then(|key| index.get(&key));
+        match matches {
+            Some(matches) => {
+                for &j in matches {
+                    matched[j] = true;
+                    let right_cells = right_columns.iter().map(|&c| &right_rows[j][c]);
+                    records.push(cells.iter().copied().chain(right_cells).collect());
+                }
+            }
+            None if matches!(join.kind, JoinKind::Inner) => {}
+            None => {
+                let empty = right_columns.iter().map(|_| "");
+                records.push(cells.iter().copied().chain(empty).collect());
+            }
+        }
+    }
+    if matches!(join.kind, JoinKind::Full) {
+        for (record, _) in right_rows.iter().zip(matched).filter(|(_, m)| !*m) {
+            // the key cells of the left side are taken from the right row
+            let cells =
+                (0..left_header.len()).map(|i| match left_keys.iter().position(|&k| k == i) {
+                    Some(k) => &record[right_keys[k]],
+                    None => "",
+                });
+            let right_cells = right_columns.iter().map(|&c| &record[c]);
+            records.push(cells.chain(right_cells).collect());
+        }
+    }
+    convert_records(left, &header, records, opts, output)
+}
+
+fn key_indices(header: &StringRecord, on: &[String], input: &str) -> anyhow::Result<Vec<usize>> {
+    on.iter()
+        .map(|column| {
+            header.iter().position(|h| h == column).ok_or(anyhow!(
+                "column {} does not exist in {}",
+                column,
+                input
+            ))
+        })
+        .collect()
+}
+
+// like SQL, a row with an empty key cell never matches
+fn key<'a>(record: &'a StringRecord, indices: &[usize]) -> Option<Vec<&'a str>> {
+    indices
+        .iter()
+        .map(|&i| record.get(i).filter(|v| !is_null(v)))
+        .collect()
+}
+
+// a prefixed name may already be taken, e.g. right_id next to a left column right_id
+fn joined_header(
+    left: &StringRecord,
+    right: &StringRecord,
+    right_columns: &[usize],
+    join: &JoinOpts,
+) -> anyhow::Result<StringRecord> {
+    let right_names = right_columns.iter().map(|&i| &right[i]).collect::<Vec<_>>();
+    let left_names = left.iter().map(|name| {
+        if right_names.contains(&name) {
+            format!("{}{}", join.left_prefix, name)
+        } else {
+            name.to_owned()
+        }
+    });
+    let right_names = right_names.iter().map(|&name| {
+        if left.iter().any(|h| h == name) {
+            format!("{}{}", join.right_prefix, name)
+        } else {
+            name.to_owned()
+        }
+    });
+    let header = left_names.chain(right_names).collect::<StringRecord>();
+    let mut seen = HashSet::new();
+    match header.iter().find(|name| !seen.insert(*name)) {
+        Some(name) => Err(anyhow!(
+            "column {} would appear twice in the joined output, \
+             choose other --left-prefix or --right-prefix",
+            name
+        )),
+        None => Ok(header),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn join(kind: JoinKind) -> anyhow::Result<Vec<Value>> {
+        let join = JoinOpts {
+            on: vec!["Name".into()],
+            kind,
+            ..Default::default()
+        };
+        let mut content = Vec::new();
+        process_csv_join(
+            "assets/juventus.csv",
+            "fixtures/caps.csv",
+            &CsvDialect::default(),
+            &join,
+            &Default::default(),
+            &mut content,
+        )?;
+        Ok(serde_json::from_slice(&content)?)
+    }
+
+    #[test]
+    fn test_process_csv_join() -> anyhow::Result<()> {
+        let rows = join(JoinKind::Inner)?;
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0]["Name"], "Gianluigi Buffon");
+        assert_eq!(rows[0]["left_Nationality"], "Italy");
+        assert_eq!(rows[0]["right_Nationality"], "Italy");
+        assert_eq!(rows[0]["Caps"], 176);
+
+        let rows = join(JoinKind::Left)?;
+        assert_eq!(rows.len(), 27);
+        assert_eq!(rows[0]["Name"], "Wojciech Szczesny");
+        assert_eq!(rows[0]["Caps"], Value::Null);
+
+        let rows = join(JoinKind::Full)?;
+        assert_eq!(rows.len(), 28);
+        assert_eq!(rows[27]["Name"], "Zlatan Ibrahimovic");
+        assert_eq!(rows[27]["Kit Number"], Value::Null);
+        assert_eq!(rows[27]["right_Nationality"], "Sweden");
+        Ok(())
+    }
+
+    #[test]
+    fn test_joined_header_collision() -> anyhow::Result<()> {
+        let left = StringRecord::from(vec!["id", "name", "right_name"]);
+        let right = StringRecord::from(vec!["id", "name"]);
+        let join = JoinOpts::default();
+        assert!(joined_header(&left, &right, &[1], &join).is_err());
+
+        let join = JoinOpts {
+            right_prefix: "other_".into(),
+            ..Default::default()
+        };
+        let header = joined_header(&left, &right, &[1], &join)?;
+        assert_eq!(header, vec!["id", "left_name", "right_name", "other_name"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_join_missing_key() {
+        let join = JoinOpts {
+            on: vec!["Club".into()],
+            ..Default::default()
+        };
+        let result = process_csv_join(
+            "assets/juventus.csv",
+            "fixtures/caps.csv",
+            &CsvDialect::default(),
+            &join,
+            &Default::default(),
+            &mut Vec::new(),
+        );
+        assert!(result.is_err());
+    }
+}
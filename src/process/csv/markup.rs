@@ -0,0 +1,155 @@
+use std::{io::Write, path::Path};
+
+use csv::StringRecord;
+use serde_json::Value;
+
+use crate::cli::ColumnType;
+
+use super::writer::{Row, RowWriter};
+
+// github flavored table, numbers are right aligned and booleans centered
+pub struct MarkdownWriter<'a> {
+    pub w: Box<dyn Write + 'a>,
+}
+
+impl RowWriter for MarkdownWriter<'_> {
+    fn begin(&mut self, header: &StringRecord, types: &[Option<ColumnType>]) -> anyhow::Result<()> {
+        let names = header.iter().map(escape_markdown).collect::<Vec<_>>();
+        let aligns = types
+            .iter()
+            .map(|ty| match ty {
+                Some(ColumnType::Int) | Some(ColumnType::Float) => "---:",
+                Some(ColumnType::Bool) => ":---:",
+                _ => "---",
+            })
+            .collect::<Vec<_>>();
+        writeln!(self.w, "| {} |", names.join(" | "))?;
+        writeln!(self.w, "| {} |", aligns.join(" | "))?;
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &Row) -> anyhow::Result<()> {
+        let cells = row
+            .values()
+            .map(|v| escape_markdown(&cell(v)))
+            .collect::<Vec<_>>();
+        writeln!(self.w, "| {} |", cells.join(" | "))?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.w.flush()?;
+        Ok(())
+    }
+}
+
+// a standalone page with one table, numeric columns are right aligned
+pub struct HtmlWriter<'a> {
+    pub w: Box<dyn Write + 'a>,
+    pub title: String,
+    pub numeric: Vec<bool>,
+}
+
+impl RowWriter for HtmlWriter<'_> {
+    fn begin(&mut self, header: &StringRecord, types: &[Option<ColumnType>]) -> anyhow::Result<()> {
+        self.numeric = types
+            .iter()
+            .map(|ty| matches!(ty, Some(ColumnType::Int) | Some(ColumnType::Float)))
+            .collect();
+        writeln!(self.w, "<!DOCTYPE html>")?;
+        writeln!(self.w, "<html>")?;
+        writeln!(self.w, "<head>")?;
+        writeln!(self.w, "<meta charset=\"utf-8\">")?;
+        writeln!(self.w, "<title>{}</title>", escape_html(&self.title))?;
+        writeln!(self.w, "</head>")?;
+        writeln!(self.w, "<body>")?;
+        writeln!(self.w, "<table>")?;
+        writeln!(self.w, "<thead>")?;
+        writeln!(self.w, "<tr>")?;
+        for (i, name) in header.iter().enumerate() {
+            writeln!(self.w, "  <th{}>{}</th>", self.align(i), escape_html(name))?;
+        }
+        writeln!(self.w, "</tr>")?;
+        writeln!(self.w, "</thead>")?;
+        writeln!(self.w, "<tbody>")?;
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &Row) -> anyhow::Result<()> {
+        writeln!(self.w, "<tr>")?;
+        for (i, value) in row.values().enumerate() {
+            let cell = escape_html(&cell(value));
+            writeln!(self.w, "  <td{}>{}</td>", self.align(i), cell)?;
+        }
+        writeln!(self.w, "</tr>")?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        writeln!(self.w, "</tbody>")?;
+        writeln!(self.w, "</table>")?;
+        writeln!(self.w, "</body>")?;
+        writeln!(self.w, "</html>")?;
+        self.w.flush()?;
+        Ok(())
+    }
+}
+
+impl HtmlWriter<'_> {
+    fn align(&self, i: usize) -> &'static str {
+        match self.numeric.get(i) {
+            Some(true) => " style=\"text-align: right\"",
+            _ => "",
+        }
+    }
+}
+
+// "assets/juventus.csv" -> "juventus.csv"
+pub fn title(input: &str) -> String {
+    match input {
+        "-" => "stdin".into(),
+        input => Path::new(input)
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or(input)
+            .into(),
+    }
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+// a pipe would end the cell and a newline the row
+fn escape_markdown(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('|', "\\|")
+        .replace("\r\n", "<br>")
+        .replace(['\r', '\n'], "<br>")
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape_markdown("a|b\nc"), "a\\|b<br>c");
+        assert_eq!(
+            escape_html("<b>\"Tom & Jerry's\"</b>"),
+            "&lt;b&gt;&quot;Tom &amp; Jerry&#39;s&quot;&lt;/b&gt;"
+        );
+    }
+}
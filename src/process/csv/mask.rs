@@ -0,0 +1,137 @@
+use anyhow::anyhow;
+use csv::StringRecord;
+use regex::Regex;
+
+use crate::{
+    cli::{ColumnType, MaskKind},
+    process::signer_verifier::{Blake3, TextSigner},
+};
+
+use super::infer::is_null;
+
+const REDACTED: &str = "***";
+
+// masks are applied to output rows only, so --where, --sort-by and the schema see raw values;
+// empty cells stay empty
+pub struct Masker {
+    columns: Vec<(usize, MaskKind)>,
+    signer: Option<Blake3>,
+    year: Regex,
+}
+
+impl Masker {
+    // the key is only needed for blake3 pseudonyms, at least its first 32 bytes are used
+    pub fn new(
+        header: &StringRecord,
+        masks: &[(String, MaskKind)],
+        key: Option<&str>,
+    ) -> anyhow::Result<Self> {
+        let columns = masks
+            .iter()
+            .map(|(column, kind)| {
+                let index = header
+                    .iter()
+                    .position(|h| h == column)
+                    .ok_or(anyhow!("mask column {} is not in the output", column))?;
+                Ok((index, *kind))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let signer = match key {
+            Some(key) if key.len() < 32 => {
+                return Err(anyhow!("mask key must be at least 32 bytes"));
+            }
+            Some(key) => Some(Blake3::try_new(key)?),
+            None if columns.iter().any(|(_, k)| matches!(k, MaskKind::Blake3)) => {
+                return Err(anyhow!("blake3 masks need a key, see --mask-key"));
+            }
+            None => None,
+        };
+        Ok(Self {
+            columns,
+            signer,
+            year: Regex::new(r"(?:^|\D)(\d{4})(?:\D|$)")?,
+        })
+    }
+
+    pub fn apply(&self, record: StringRecord) -> anyhow::Result<StringRecord> {
+        if self.columns.is_empty() {
+            return Ok(record);
+        }
+        let mut cells = record.iter().map(String::from).collect::<Vec<_>>();
+        for (index, kind) in self.columns.iter() {
+            let Some(value) = cells.get(*index).filter(|v| !is_null(v)) else {
+                continue;
+            };
+            let masked = match kind {
+                MaskKind::Blake3 => match &self.signer {
+                    Some(signer) => signer.sign(value)?,
+                    None => return Err(anyhow!("blake3 masks need a key")),
+                },
+                MaskKind::Redact => REDACTED.to_owned(),
+                // no year means nothing is left to show
+                MaskKind::YearOnly => self
+                    .year
+                    .captures(value)
+                    .map(|c| c[1].to_owned())
+                    .unwrap_or_default(),
+            };
+            cells[*index] = masked;
+        }
+        let mut masked = cells.into_iter().collect::<StringRecord>();
+        masked.set_position(record.position().cloned());
+        Ok(masked)
+    }
+
+    // masked cells no longer have the type inferred from the raw values
+    pub fn mask_types(&self, types: &mut [Option<ColumnType>], infer: bool) {
+        for (index, kind) in self.columns.iter() {
+            types[*index] = match kind {
+                MaskKind::YearOnly if infer => Some(ColumnType::Int),
+                _ => None,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "eyW2pW29DLaVHe8N3@^Ve?*k@sbEgNFq";
+
+    #[test]
+    fn test_masker() -> anyhow::Result<()> {
+        let header = StringRecord::from(vec!["Name", "Email", "DOB"]);
+        let masks = vec![
+            ("Name".to_owned(), MaskKind::Blake3),
+            ("Email".to_owned(), MaskKind::Redact),
+            ("DOB".to_owned(), MaskKind::YearOnly),
+        ];
+        let masker = Masker::new(&header, &masks, Some(KEY))?;
+        let record =
+            StringRecord::from(vec!["Paulo Dybala", "pd@juventus.com", "Nov 15, 1993 (25)"]);
+        let masked = masker.apply(record.clone())?;
+        let pseudonym = Blake3::try_new(KEY)?.sign("Paulo Dybala")?;
+        assert_eq!(masked, vec![pseudonym.as_str(), "***", "1993"]);
+        // the same value and key always give the same pseudonym
+        assert_eq!(masker.apply(record)?, masked);
+
+        let record = StringRecord::from(vec!["", "", "unknown"]);
+        assert_eq!(masker.apply(record)?, vec!["", "", ""]);
+
+        let mut types = vec![Some(ColumnType::String); 3];
+        masker.mask_types(&mut types, true);
+        assert_eq!(types, vec![None, None, Some(ColumnType::Int)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_masker_errors() {
+        let header = StringRecord::from(vec!["Name"]);
+        let masks = vec![("Name".to_owned(), MaskKind::Blake3)];
+        assert!(Masker::new(&header, &masks, None).is_err());
+        assert!(Masker::new(&header, &masks, Some("short")).is_err());
+        let masks = vec![("Email".to_owned(), MaskKind::Redact)];
+        assert!(Masker::new(&header, &masks, None).is_err());
+    }
+}
@@ -0,0 +1,1125 @@
+mod crypt;
+mod diff;
+mod encoding;
+mod filter;
+mod from;
+mod infer;
+mod join;
+mod markup;
+mod mask;
+mod pivot;
+mod proto;
+mod reader;
+mod schema;
+mod split;
+mod sql;
+mod stats;
+mod table;
+mod transform;
+mod writer;
+
+use std::{collections::HashSet, io::Write};
+
+use anyhow::anyhow;
+use csv::StringRecord;
+use encoding_rs::Encoding;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::cli::{ColumnType, MaskKind, OnError, OutputFormat, SqlDialect};
+
+pub use crypt::{process_csv_decrypt, process_csv_encrypt, process_csv_generate_key};
+pub use diff::{process_csv_diff, CellChange, ChangedRow, CsvDiff};
+use filter::RowQuery;
+pub use filter::{Predicate, SortKey};
+pub use from::process_csv_from;
+use infer::TypeInference;
+pub use join::{process_csv_join, JoinOpts};
+use mask::Masker;
+pub use pivot::{process_csv_pivot, CsvPivot, PivotOpts};
+use reader::{fit_record, CsvInputs, Records};
+pub use reader::{process_csv_write_quarantine, CsvDialect, MalformedRow};
+use schema::Validator;
+pub use schema::{process_csv_validate, ColumnRule, CsvSchema, Violation};
+pub use split::{process_csv_split, SplitBy, SplitFile, SplitOpts};
+pub use stats::{process_csv_stats, ColumnStats, CsvStats};
+pub use table::{process_csv_show, TableOpts};
+use transform::Transformer;
+pub use transform::{Transform, TransformRule};
+use writer::{row_writer, Row, RowWriter};
+
+// rows buffered from stdin to infer column types before streaming starts
+const INFER_SAMPLE_ROWS: usize = 1000;
+
+#[allow(dead_code)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Player {
+    name: String,
+    position: String,
+    #[serde(rename = "DOB")]
+    dob: String,
+    nationality: String,
+    #[serde(rename = "Kit Number")]
+    kit: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct CsvConvertOpts {
+    pub format: OutputFormat,
+    // TOML has no top-level arrays, rows are written as `[[<toml_table>]]`
+    pub toml_table: String,
+    // TOML has no null, empty cells are omitted unless this is set
+    pub toml_keep_empty: bool,
+    // message name of the generated .proto schema, defaults to the input file name
+    pub proto_message: Option<String>,
+    // when false every cell without an explicit type is emitted as a string
+    pub infer: bool,
+    // column types that win over inference, e.g. ("Kit Number", Int)
+    pub types: Vec<(String, ColumnType)>,
+    // select and reorder columns, empty keeps all of them in header order
+    pub columns: Vec<String>,
+    // rows must match every predicate, checked on the raw cells before projection
+    pub filters: Vec<Predicate>,
+    pub sort_by: Vec<SortKey>,
+    pub limit: Option<usize>,
+    // rows violating the schema are left out of the output and reported back
+    pub schema: Option<CsvSchema>,
+    // whether rows that fail to parse end the conversion or are reported back as malformed
+    pub on_error: OnError,
+    // column holding the input path of each row, added after the union of the input headers
+    pub source_column: Option<String>,
+    // applied first, so every other option sees the transformed cells and split columns
+    pub transforms: Vec<TransformRule>,
+    // nest rows under their values of these columns, one level per column
+    pub group_by: Vec<String>,
+    // output cells replaced by pseudonyms, "***" or their year
+    pub masks: Vec<(String, MaskKind)>,
+    // blake3 key of pseudonyms, the same key gives the same pseudonym in every file
+    pub mask_key: Option<String>,
+    // output is re-encoded from utf-8 when set
+    pub output_encoding: Option<&'static Encoding>,
+    pub sql_dialect: SqlDialect,
+    // table of CREATE TABLE and INSERT, defaults to the input file name
+    pub sql_table: Option<String>,
+    // rows per INSERT statement
+    pub sql_batch: usize,
+    // rows are inserted into a table of this SQLite database instead of being written out
+    pub sqlite_db: Option<String>,
+}
+
+pub struct CsvOutput {
+    pub rows: usize,
+    // only set for proto, which needs the .proto schema to decode the output
+    pub schema: Option<String>,
+    // schema violations of the rows left out
+    pub rejected: Vec<Violation>,
+    // rows that failed to parse, only with --on-error skip or quarantine
+    pub malformed: Vec<MalformedRow>,
+}
+
+impl Default for CsvConvertOpts {
+    fn default() -> Self {
+        Self {
+            format: OutputFormat::Json,
+            toml_table: "rows".into(),
+            toml_keep_empty: false,
+            proto_message: None,
+            infer: true,
+            types: Vec::new(),
+            columns: Vec::new(),
+            filters: Vec::new(),
+            sort_by: Vec::new(),
+            limit: None,
+            schema: None,
+            on_error: OnError::Fail,
+            source_column: None,
+            transforms: Vec::new(),
+            group_by: Vec::new(),
+            masks: Vec::new(),
+            mask_key: None,
+            output_encoding: None,
+            sql_dialect: SqlDialect::Sqlite,
+            sql_table: None,
+            sql_batch: 500,
+            sqlite_db: None,
+        }
+    }
+}
+
+impl CsvConvertOpts {
+    // proto fields are declared as string and csv cells are written back as they were read
+    fn writes_raw(&self) -> bool {
+        matches!(self.format, OutputFormat::Proto | OutputFormat::Csv)
+    }
+
+    fn should_infer(&self) -> bool {
+        self.infer && !self.writes_raw()
+    }
+}
+
+// several inputs are concatenated under the union of their headers, the first one names
+// the proto message and the sql table
+pub fn process_csv(
+    inputs: &[String],
+    dialect: &CsvDialect,
+    opts: &CsvConvertOpts,
+    output: &mut dyn Write,
+) -> Result<CsvOutput, anyhow::Error> {
+    let source = CsvInputs {
+        paths: inputs,
+        dialect,
+        source_column: opts.source_column.as_deref(),
+    };
+    let (input_header, mut records) = source.open()?;
+    let input = inputs[0].as_str();
+    let transformer = Transformer::new(&input_header, &opts.transforms)?;
+    let mut header = transformer.header();
+    let projection = Projection::new(&header, &opts.columns)?;
+    let query = RowQuery::new(&header, &opts.filters, &opts.sort_by, opts.limit)?;
+    let mut validator = match &opts.schema {
+        Some(schema) => {
+            let (validator, violations) = Validator::new(schema, &header)?;
+            if let Some(violation) = violations.first() {
+                return Err(anyhow!("{}", violation));
+            }
+            Some(validator)
+        }
+        None => None,
+    };
+    // a clone starts with no unique values seen, so inference can check rows on its own
+    let is_valid = |validator: &mut Option<Validator>, record: &StringRecord| {
+        validator
+            .as_mut()
+            .is_none_or(|v| v.check(record).is_empty())
+    };
+
+    // let mut players = Vec::new();
+    // for result in reader.deserialize() {
+    //     // Notice that we need to provide a type hint for automatic
+    //     // deserialization.
+    //     let player: Player = result?;
+    //     players.push(player);
+    // }
+
+    // for player in players {
+    //     println!("player is {:?}", player);
+    // }
+
+    let infer = opts.should_infer();
+    let mut scan_validator = validator.clone();
+    let (inference, sampled) = if infer {
+        let keep = |r: &StringRecord| is_valid(&mut scan_validator, r) && query.matches(r);
+        scan_types(
+            &source,
+            &mut records,
+            &transformer,
+            &mut header,
+            &projection,
+            keep,
+        )?
+    } else {
+        Default::default()
+    };
+    let out_header = projection.header(&header);
+    // types declared in the schema apply unless --type says otherwise
+    let overrides = validator
+        .iter()
+        .flat_map(|v| v.types())
+        .filter(|(name, _)| out_header.iter().any(|h| h == name))
+        .chain(opts.types.iter().cloned())
+        .collect::<Vec<_>>();
+    let mut types = ColumnTypes::new(&inference, &out_header, opts, &overrides)?;
+    let masker = Masker::new(&out_header, &opts.masks, opts.mask_key.as_deref())?;
+    masker.mask_types(&mut types.types, infer);
+    check_unique(&out_header)?;
+    check_group_by(&out_header, &opts.group_by)?;
+
+    let mut writer = row_writer(opts, input, output)?;
+    writer.begin(&out_header, &types.types)?;
+    let limit = query.limit.unwrap_or(usize::MAX);
+    let mut rows = 0;
+    let mut rejected = Vec::new();
+    let mut malformed = Vec::new();
+    let mut sorted = Vec::new();
+    // print!("{:?}", format);
+    let records = records.map(|r| transformer.apply(r?));
+    for result in sampled.into_iter().chain(records) {
+        // without sorting the first matches are final, the rest of the input is never read
+        if rows >= limit {
+            break;
+        }
+        let mut record = match result {
+            Ok(record) => record,
+            Err(e) => match e.downcast::<MalformedRow>() {
+                Ok(row) if !matches!(opts.on_error, OnError::Fail) => {
+                    malformed.push(row);
+                    continue;
+                }
+                Ok(row) => return Err(row.into()),
+                Err(e) => return Err(e),
+            },
+        };
+        fit_record(&mut header, &mut record);
+        if let Some(validator) = validator.as_mut() {
+            let violations = validator.check(&record);
+            if !violations.is_empty() {
+                rejected.extend(violations);
+                continue;
+            }
+        }
+        if !query.matches(&record) {
+            continue;
+        }
+        if query.is_sorted() {
+            sorted.push(record);
+            continue;
+        }
+        write_record(
+            &mut *writer,
+            &projection,
+            &masker,
+            &header,
+            &mut types,
+            &record,
+        )?;
+        rows += 1;
+    }
+    query.sort(&mut sorted);
+    for record in sorted.into_iter().take(limit) {
+        write_record(
+            &mut *writer,
+            &projection,
+            &masker,
+            &header,
+            &mut types,
+            &record,
+        )?;
+        rows += 1;
+    }
+    writer.finish()?;
+
+    let schema = proto_schema(opts, input, &projection.header(&header));
+    Ok(CsvOutput {
+        rows,
+        schema,
+        rejected,
+        malformed,
+    })
+}
+
+// rows that are already in memory, e.g. the result of a join, go through the same
+// filtering, inference and writers as a streamed file
+fn convert_records(
+    input: &str,
+    header: &StringRecord,
+    records: Vec<StringRecord>,
+    opts: &CsvConvertOpts,
+    output: &mut dyn Write,
+) -> anyhow::Result<CsvOutput> {
+    let transformer = Transformer::new(header, &opts.transforms)?;
+    let header = &transformer.header();
+    let mut records = records
+        .into_iter()
+        .map(|r| transformer.apply(r))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let projection = Projection::new(header, &opts.columns)?;
+    let query = RowQuery::new(header, &opts.filters, &opts.sort_by, opts.limit)?;
+    records.retain(|record| query.matches(record));
+    query.sort(&mut records);
+    records.truncate(query.limit.unwrap_or(usize::MAX));
+
+    let infer = opts.should_infer();
+    let mut inference = TypeInference::default();
+    if infer {
+        for record in records.iter() {
+            inference.update(&projection.apply(record));
+        }
+    }
+    let out_header = projection.header(header);
+    let mut types = ColumnTypes::new(&inference, &out_header, opts, &opts.types)?;
+    let masker = Masker::new(&out_header, &opts.masks, opts.mask_key.as_deref())?;
+    masker.mask_types(&mut types.types, infer);
+    check_unique(&out_header)?;
+    check_group_by(&out_header, &opts.group_by)?;
+
+    let mut writer = row_writer(opts, input, output)?;
+    writer.begin(&out_header, &types.types)?;
+    for record in records.iter() {
+        write_record(
+            &mut *writer,
+            &projection,
+            &masker,
+            header,
+            &mut types,
+            record,
+        )?;
+    }
+    writer.finish()?;
+    let schema = proto_schema(opts, input, &projection.header(header));
+    Ok(CsvOutput {
+        rows: records.len(),
+        schema,
+        rejected: Vec::new(),
+        malformed: Vec::new(),
+    })
+}
+
+// group columns have to be part of the output rows
+// rows are keyed by column name, so a second column of the same name would be lost
+fn check_unique(header: &StringRecord) -> anyhow::Result<()> {
+    let mut seen = HashSet::new();
+    match header.iter().find(|name| !seen.insert(*name)) {
+        Some(name) => Err(anyhow!(
+            "column {} appears more than once in the output",
+            name
+        )),
+        None => Ok(()),
+    }
+}
+
+fn check_group_by(header: &StringRecord, group_by: &[String]) -> anyhow::Result<()> {
+    match group_by.iter().find(|c| !header.iter().any(|h| h == *c)) {
+        Some(column) => Err(anyhow!("group by column {} is not in the output", column)),
+        None => Ok(()),
+    }
+}
+
+fn proto_schema(opts: &CsvConvertOpts, input: &str, header: &StringRecord) -> Option<String> {
+    match opts.format {
+        OutputFormat::Proto => {
+            let message = match &opts.proto_message {
+                Some(message) => message.clone(),
+                None => proto::message_name(input),
+            };
+            Some(proto::schema(&message, header))
+        }
+        _ => None,
+    }
+}
+
+// types are inferred over the rows `keep` accepts, either in a first pass over the file or
+// from the first rows of stdin, which can not be read again and are returned to be replayed;
+// malformed rows are left to the pass that writes the output
+fn scan_types(
+    source: &CsvInputs,
+    records: &mut Records,
+    transformer: &Transformer,
+    header: &mut StringRecord,
+    projection: &Projection,
+    mut keep: impl FnMut(&StringRecord) -> bool,
+) -> anyhow::Result<(TypeInference, Vec<anyhow::Result<StringRecord>>)> {
+    let mut inference = TypeInference::default();
+    let mut sampled = Vec::new();
+    if source.is_stdin() {
+        for result in records.take(INFER_SAMPLE_ROWS) {
+            let mut record = match result {
+                Ok(record) => transformer.apply(record)?,
+                Err(e) if e.is::<MalformedRow>() => {
+                    sampled.push(Err(e));
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            fit_record(header, &mut record);
+            if keep(&record) {
+                inference.update(&projection.apply(&record));
+            }
+            sampled.push(Ok(record));
+        }
+    } else {
+        // the first pass keeps inference exact without holding the rows
+        let (_, scan) = source.open()?;
+        let mut scan_header = transformer.header();
+        for result in scan {
+            let mut record = match result {
+                Ok(record) => transformer.apply(record)?,
+                Err(e) if e.is::<MalformedRow>() => continue,
+                Err(e) => return Err(e),
+            };
+            fit_record(&mut scan_header, &mut record);
+            if keep(&record) {
+                inference.update(&projection.apply(&record));
+            }
+        }
+    }
+    Ok((inference, sampled))
+}
+
+fn write_record(
+    writer: &mut dyn RowWriter,
+    projection: &Projection,
+    masker: &Masker,
+    header: &StringRecord,
+    types: &mut ColumnTypes,
+    record: &StringRecord,
+) -> anyhow::Result<()> {
+    let out_header = projection.header(header);
+    // columns only seen in later flexible rows keep their raw strings
+    types.resize(out_header.len());
+    let record = masker.apply(projection.apply(record))?;
+    let row = to_row(&out_header, &record, types)?;
+    writer.write_row(&row)
+}
+
+// the type each output cell is parsed as
+struct ColumnTypes {
+    types: Vec<Option<ColumnType>>,
+    // set by --type or the schema, a cell that does not parse is an error; an inferred type
+    // may have seen only the stdin sample, so a later cell that does not fit stays a string
+    declared: Vec<bool>,
+    // declared types are checked but cells are written as they were read, see writes_raw
+    raw: bool,
+}
+
+impl ColumnTypes {
+    fn new(
+        inference: &TypeInference,
+        header: &StringRecord,
+        opts: &CsvConvertOpts,
+        overrides: &[(String, ColumnType)],
+    ) -> anyhow::Result<Self> {
+        let types = inference.types(header, opts.should_infer(), overrides)?;
+        let declared = header
+            .iter()
+            .map(|h| overrides.iter().any(|(name, _)| name == h))
+            .collect();
+        Ok(Self {
+            types,
+            declared,
+            raw: opts.writes_raw(),
+        })
+    }
+
+    fn resize(&mut self, len: usize) {
+        self.types.resize(len, None);
+        self.declared.resize(len, false);
+    }
+}
+
+// select and reorder columns by name, or keep every column when no names are given
+struct Projection {
+    indices: Option<Vec<usize>>,
+}
+
+impl Projection {
+    fn new(header: &StringRecord, columns: &[String]) -> anyhow::Result<Self> {
+        if columns.is_empty() {
+            return Ok(Self { indices: None });
+        }
+        let indices = columns
+            .iter()
+            .map(|c| {
+                header
+                    .iter()
+                    .position(|h| h == c)
+                    .ok_or(anyhow!("column {} does not exist", c))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self {
+            indices: Some(indices),
+        })
+    }
+
+    fn header(&self, header: &StringRecord) -> StringRecord {
+        self.apply(header)
+    }
+
+    fn apply(&self, record: &StringRecord) -> StringRecord {
+        let Some(indices) = &self.indices else {
+            return record.clone();
+        };
+        let mut projected = indices
+            .iter()
+            .map(|&i| record.get(i).unwrap_or_default())
+            .collect::<StringRecord>();
+        // keep the line number for error messages
+        projected.set_position(record.position().cloned());
+        projected
+    }
+}
+
+fn to_row(
+    header: &StringRecord,
+    record: &StringRecord,
+    types: &ColumnTypes,
+) -> anyhow::Result<Row> {
+    // let mut map = std::collections::HashMap::new();
+    // for i in 0..header.len() {
+    //     map.insert(header[i].to_string(), serde_json::json!(record[i]));
+    // }
+    // let json_value = serde_json::json!(map);
+    // records.push(json_value);
+    let row = header
+        .iter()
+        .zip(record.iter())
+        .zip(types.types.iter().zip(types.declared.iter()))
+        .map(|((h, r), (ty, declared))| {
+            let value = match ty.map(|ty| infer::to_value(r, ty)) {
+                None => Value::String(r.to_owned()),
+                Some(Ok(_)) if types.raw => Value::String(r.to_owned()),
+                Some(Ok(value)) => value,
+                Some(Err(_)) if !declared => Value::String(r.to_owned()),
+                Some(Err(e)) => {
+                    let line = record.position().map(|p| p.line()).unwrap_or_default();
+                    return Err(anyhow!("line {}, column {}: {}", line, h, e));
+                }
+            };
+            Ok((h.to_owned(), value)) // 创建键值对，拥有字符串
+        })
+        .collect::<anyhow::Result<Row>>()?;
+    // a col_n name given to an extra flexible cell may already be taken
+    if row.len() < header.len().min(record.len()) {
+        check_unique(header)?;
+    }
+    Ok(row)
+}
+
+// duckdb
+// select * from read_csv('assets/juventus.csv', auto_detect=true);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::CsvTrim;
+    use serde_json::Map;
+
+    fn convert(
+        input: &str,
+        dialect: &CsvDialect,
+        opts: &CsvConvertOpts,
+    ) -> anyhow::Result<(Vec<u8>, CsvOutput)> {
+        let mut content = Vec::new();
+        let output = process_csv(&[input.to_owned()], dialect, opts, &mut content)?;
+        Ok((content, output))
+    }
+
+    #[test]
+    fn test_process_csv_toml() -> anyhow::Result<()> {
+        let opts = CsvConvertOpts {
+            format: OutputFormat::Toml,
+            toml_table: "players".into(),
+            ..Default::default()
+        };
+        let content =
+            String::from_utf8(convert("assets/juventus.csv", &CsvDialect::default(), &opts)?.0)?;
+        let doc: toml::Table = toml::from_str(&content)?;
+        let players = doc["players"].as_array().unwrap();
+        assert_eq!(players.len(), 27);
+        assert_eq!(players[0]["Name"].as_str(), Some("Wojciech Szczesny"));
+        assert_eq!(players[0]["Kit Number"].as_integer(), Some(1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_toml_empty_cells() -> anyhow::Result<()> {
+        let mut opts = CsvConvertOpts {
+            format: OutputFormat::Toml,
+            ..Default::default()
+        };
+        let content = String::from_utf8(
+            convert("fixtures/empty_cells.csv", &CsvDialect::default(), &opts)?.0,
+        )?;
+        let doc: toml::Table = toml::from_str(&content)?;
+        let rows = doc["rows"].as_array().unwrap();
+        assert!(rows[0].get("Nationality").is_none());
+
+        opts.toml_keep_empty = true;
+        let content = String::from_utf8(
+            convert("fixtures/empty_cells.csv", &CsvDialect::default(), &opts)?.0,
+        )?;
+        let doc: toml::Table = toml::from_str(&content)?;
+        let rows = doc["rows"].as_array().unwrap();
+        assert_eq!(rows[0]["Nationality"].as_str(), Some(""));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_proto() -> anyhow::Result<()> {
+        let opts = CsvConvertOpts {
+            format: OutputFormat::Proto,
+            ..Default::default()
+        };
+        let (content, output) = convert("assets/juventus.csv", &CsvDialect::default(), &opts)?;
+        let schema = output.schema.unwrap();
+        assert!(schema.contains("message Juventus {"));
+        assert!(schema.contains("  string kit_number = 5;"));
+
+        // first row: len, then field 1 (name) as tag 0x0a + len + bytes
+        let name = "Wojciech Szczesny";
+        assert_eq!(content[1], 0x0a);
+        assert_eq!(content[2] as usize, name.len());
+        assert_eq!(&content[3..3 + name.len()], name.as_bytes());
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_raw_formats_keep_typed_cells() -> anyhow::Result<()> {
+        let dialect = CsvDialect::default();
+        for format in [OutputFormat::Proto, OutputFormat::Csv] {
+            let opts = CsvConvertOpts {
+                format,
+                ..Default::default()
+            };
+            let (untyped, _) = convert("assets/juventus.csv", &dialect, &opts)?;
+            let opts = CsvConvertOpts {
+                format,
+                types: vec![("Kit Number".into(), ColumnType::Int)],
+                ..Default::default()
+            };
+            let (typed, _) = convert("assets/juventus.csv", &dialect, &opts)?;
+            assert_eq!(typed, untyped);
+        }
+
+        let path = std::env::temp_dir().join("rcli_typed_nulls.csv");
+        std::fs::write(&path, "id,score\n1,null\n2,\n3,NULL\n")?;
+        let opts = CsvConvertOpts {
+            format: OutputFormat::Csv,
+            types: vec![("score".into(), ColumnType::Int)],
+            ..Default::default()
+        };
+        let (content, _) = convert(&path.to_string_lossy(), &dialect, &opts)?;
+        assert_eq!(content, std::fs::read(&path)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_row_inferred_type_falls_back_to_string() -> anyhow::Result<()> {
+        let header = StringRecord::from(vec!["v"]);
+        let mut inference = TypeInference::default();
+        inference.update(&StringRecord::from(vec!["1"]));
+        let types = ColumnTypes::new(&inference, &header, &Default::default(), &[])?;
+        let row = to_row(&header, &StringRecord::from(vec!["2"]), &types)?;
+        assert_eq!(row["v"], 2);
+        let row = to_row(&header, &StringRecord::from(vec!["abc"]), &types)?;
+        assert_eq!(row["v"], "abc");
+
+        let declared = [("v".to_owned(), ColumnType::Int)];
+        let types = ColumnTypes::new(&inference, &header, &Default::default(), &declared)?;
+        assert!(to_row(&header, &StringRecord::from(vec!["abc"]), &types).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_infer_types() -> anyhow::Result<()> {
+        let dialect = CsvDialect::default();
+        let (content, _) = convert("assets/juventus.csv", &dialect, &Default::default())?;
+        let rows: Vec<Value> = serde_json::from_slice(&content)?;
+        assert_eq!(rows[0]["Kit Number"], 1);
+
+        let opts = CsvConvertOpts {
+            infer: false,
+            ..Default::default()
+        };
+        let (content, _) = convert("assets/juventus.csv", &dialect, &opts)?;
+        let rows: Vec<Value> = serde_json::from_slice(&content)?;
+        assert_eq!(rows[0]["Kit Number"], "1");
+
+        let opts = CsvConvertOpts {
+            types: vec![("Kit Number".into(), ColumnType::Float)],
+            ..Default::default()
+        };
+        let (content, _) = convert("assets/juventus.csv", &dialect, &opts)?;
+        let rows: Vec<Value> = serde_json::from_slice(&content)?;
+        assert_eq!(rows[0]["Kit Number"], 1.0);
+
+        let opts = CsvConvertOpts {
+            types: vec![("Name".into(), ColumnType::Int)],
+            ..Default::default()
+        };
+        assert!(convert("assets/juventus.csv", &dialect, &opts).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_column_order() -> anyhow::Result<()> {
+        let dialect = CsvDialect::default();
+        let (content, _) = convert("assets/juventus.csv", &dialect, &Default::default())?;
+        let rows: Vec<Map<String, Value>> = serde_json::from_slice(&content)?;
+        let keys = rows[0].keys().collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec!["Name", "Position", "DOB", "Nationality", "Kit Number"]
+        );
+
+        let opts = CsvConvertOpts {
+            columns: vec!["Kit Number".into(), "Name".into()],
+            ..Default::default()
+        };
+        let (content, _) = convert("assets/juventus.csv", &dialect, &opts)?;
+        let rows: Vec<Map<String, Value>> = serde_json::from_slice(&content)?;
+        let keys = rows[0].keys().collect::<Vec<_>>();
+        assert_eq!(keys, vec!["Kit Number", "Name"]);
+        assert_eq!(rows[0]["Kit Number"], 1);
+
+        let opts = CsvConvertOpts {
+            columns: vec!["Age".into()],
+            ..Default::default()
+        };
+        assert!(convert("assets/juventus.csv", &dialect, &opts).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_streaming_formats() -> anyhow::Result<()> {
+        let dialect = CsvDialect::default();
+        let (content, output) = convert("assets/juventus.csv", &dialect, &Default::default())?;
+        assert_eq!(output.rows, 27);
+        // streamed json is laid out exactly like a pretty printed Vec
+        let rows: Vec<Value> = serde_json::from_slice(&content)?;
+        assert_eq!(
+            String::from_utf8(content)?,
+            serde_json::to_string_pretty(&rows)?
+        );
+
+        let opts = CsvConvertOpts {
+            format: OutputFormat::Ndjson,
+            ..Default::default()
+        };
+        let (content, _) = convert("assets/juventus.csv", &dialect, &opts)?;
+        let lines = String::from_utf8(content)?;
+        let lines = lines.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 27);
+        let row: Value = serde_json::from_str(lines[1])?;
+        assert_eq!(row, rows[1]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_where_sort_limit() -> anyhow::Result<()> {
+        let dialect = CsvDialect::default();
+        let opts = CsvConvertOpts {
+            filters: vec![
+                r#"Position == "Goalkeeper""#.parse::<Predicate>().map_err(anyhow::Error::msg)?,
+            ],
+            sort_by: vec!["Kit Number:desc"
+                .parse::<SortKey>()
+                .map_err(anyhow::Error::msg)?],
+            ..Default::default()
+        };
+        let (content, output) = convert("assets/juventus.csv", &dialect, &opts)?;
+        let rows: Vec<Value> = serde_json::from_slice(&content)?;
+        assert_eq!(output.rows, 4);
+        let kits = rows
+            .iter()
+            .map(|r| r["Kit Number"].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(kits, vec![77, 37, 31, 1]);
+
+        let opts = CsvConvertOpts {
+            filters: vec!["Name =~ '^G'"
+                .parse::<Predicate>()
+                .map_err(anyhow::Error::msg)?],
+            columns: vec!["Name".into()],
+            limit: Some(1),
+            ..Default::default()
+        };
+        let (content, output) = convert("assets/juventus.csv", &dialect, &opts)?;
+        let rows: Vec<Value> = serde_json::from_slice(&content)?;
+        assert_eq!(output.rows, 1);
+        assert_eq!(rows[0]["Name"], "Gianluigi Buffon");
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_schema_rejects_rows() -> anyhow::Result<()> {
+        let opts = CsvConvertOpts {
+            schema: Some(CsvSchema::load("fixtures/juventus.schema.yaml")?),
+            ..Default::default()
+        };
+        let dialect = CsvDialect {
+            flexible: true,
+            ..Default::default()
+        };
+        let (content, output) = convert("fixtures/invalid.csv", &dialect, &opts)?;
+        let rows: Vec<Value> = serde_json::from_slice(&content)?;
+        // the short last row is padded with empty cells, which the schema allows
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0]["Kit Number"], 1);
+        assert_eq!(rows[1]["Name"], "Mario Mandzukic");
+        let lines = output.rejected.iter().map(|v| v.line).collect::<Vec<_>>();
+        assert_eq!(lines, vec![3, 4, 4, 5]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_group_by() -> anyhow::Result<()> {
+        let dialect = CsvDialect::default();
+        let opts = CsvConvertOpts {
+            group_by: vec!["Nationality".into(), "Position".into()],
+            ..Default::default()
+        };
+        let (content, output) = convert("assets/juventus.csv", &dialect, &opts)?;
+        assert_eq!(output.rows, 27);
+        let doc: Map<String, Value> = serde_json::from_slice(&content)?;
+        // groups follow the order they first appear in
+        assert_eq!(doc.keys().next().map(|k| k.as_str()), Some("Poland"));
+        let keepers = doc["Italy"]["Goalkeeper"].as_array().unwrap();
+        assert_eq!(keepers.len(), 3);
+        assert_eq!(keepers[0]["Name"], "Mattia Perin");
+        assert_eq!(keepers[0]["Kit Number"], 37);
+
+        let opts = CsvConvertOpts {
+            format: OutputFormat::Yaml,
+            group_by: vec!["Position".into()],
+            ..Default::default()
+        };
+        let (content, _) = convert("assets/juventus.csv", &dialect, &opts)?;
+        let doc: Map<String, Value> = serde_yaml::from_slice(&content)?;
+        assert_eq!(doc["Goalkeeper"].as_array().unwrap().len(), 4);
+
+        let opts = CsvConvertOpts {
+            group_by: vec!["Position".into()],
+            columns: vec!["Name".into()],
+            ..Default::default()
+        };
+        assert!(convert("assets/juventus.csv", &dialect, &opts).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_dialect() -> anyhow::Result<()> {
+        let dialect = CsvDialect {
+            delimiter: b';',
+            comment: Some(b'#'),
+            trim: CsvTrim::All,
+            flexible: true,
+            has_headers: false,
+            ..Default::default()
+        };
+        let (content, _) = convert("fixtures/semicolon.csv", &dialect, &Default::default())?;
+        let rows: Vec<Value> = serde_json::from_slice(&content)?;
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0]["col_1"], "Wojciech Szczesny");
+        assert_eq!(rows[0]["col_3"], Value::Null);
+        assert_eq!(rows[2]["col_3"], "1,5");
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_encoding() -> anyhow::Result<()> {
+        let opts = CsvConvertOpts {
+            format: OutputFormat::Csv,
+            output_encoding: Some(encoding_rs::GBK),
+            ..Default::default()
+        };
+        let (content, _) = convert("fixtures/gbk.csv", &CsvDialect::default(), &opts)?;
+        assert_eq!(content, std::fs::read("fixtures/gbk.csv")?);
+
+        let (content, _) = convert(
+            "fixtures/gbk.csv",
+            &CsvDialect::default(),
+            &Default::default(),
+        )?;
+        let rows: Vec<Value> = serde_json::from_slice(&content)?;
+        assert_eq!(rows[0]["姓名"], "布冯");
+        assert_eq!(rows[0]["号码"], 77);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_sql() -> anyhow::Result<()> {
+        let opts = CsvConvertOpts {
+            format: OutputFormat::Sql,
+            sql_dialect: SqlDialect::Postgres,
+            sql_batch: 10,
+            ..Default::default()
+        };
+        let (content, output) = convert("assets/juventus.csv", &CsvDialect::default(), &opts)?;
+        let content = String::from_utf8(content)?;
+        assert_eq!(output.rows, 27);
+        assert!(content.starts_with("CREATE TABLE \"juventus\" (\n  \"Name\" TEXT,"));
+        assert!(content.contains("  \"Kit Number\" BIGINT\n);"));
+        assert_eq!(content.matches("INSERT INTO \"juventus\"").count(), 3);
+        assert!(content
+            .contains("('Wojciech Szczesny', 'Goalkeeper', 'Apr 18, 1990 (29)', 'Poland', 1)"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_sqlite_db() -> anyhow::Result<()> {
+        let path = std::env::temp_dir().join("rcli_juventus.db");
+        let _ = std::fs::remove_file(&path);
+        let opts = CsvConvertOpts {
+            sqlite_db: Some(path.to_string_lossy().into_owned()),
+            sql_table: Some("players".into()),
+            ..Default::default()
+        };
+        let (content, output) = convert("assets/juventus.csv", &CsvDialect::default(), &opts)?;
+        assert!(content.is_empty());
+        assert_eq!(output.rows, 27);
+
+        let conn = rusqlite::Connection::open(&path)?;
+        let (count, kits): (i64, i64) = conn.query_row(
+            "SELECT count(*), sum(\"Kit Number\") FROM players WHERE Nationality = 'Italy'",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        assert!(count > 0 && kits > 0);
+        // the table is not replaced by a second run
+        assert!(convert("assets/juventus.csv", &CsvDialect::default(), &opts).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_duplicate_columns() -> anyhow::Result<()> {
+        let input = std::env::temp_dir().join("rcli_duplicate_columns.csv");
+        std::fs::write(&input, "a,a,b\n1,2,3\n")?;
+        let input = input.to_string_lossy();
+        for format in [OutputFormat::Csv, OutputFormat::Sql] {
+            let opts = CsvConvertOpts {
+                format,
+                ..Default::default()
+            };
+            let Err(err) = convert(&input, &CsvDialect::default(), &opts) else {
+                panic!("the second a column would be lost");
+            };
+            assert_eq!(
+                err.to_string(),
+                "column a appears more than once in the output"
+            );
+        }
+
+        // an extra flexible cell is named col_3, which the header already has
+        std::fs::write(input.as_ref(), "a,col_3\n1,2,3\n")?;
+        let dialect = CsvDialect {
+            flexible: true,
+            ..Default::default()
+        };
+        assert!(convert(&input, &dialect, &Default::default()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_on_error() -> anyhow::Result<()> {
+        // utf-8 has to be given, a guess would read the bad byte as windows-1252
+        let dialect = CsvDialect {
+            encoding: Some(encoding_rs::UTF_8),
+            ..Default::default()
+        };
+        let Err(err) = convert("fixtures/malformed.csv", &dialect, &Default::default()) else {
+            panic!("the ragged row should fail the conversion");
+        };
+        assert_eq!(
+            err.to_string(),
+            "fixtures/malformed.csv: line 3: expected 3 fields, found 2"
+        );
+
+        let opts = CsvConvertOpts {
+            on_error: OnError::Quarantine,
+            ..Default::default()
+        };
+        let (content, output) = convert("fixtures/malformed.csv", &dialect, &opts)?;
+        let rows: Vec<Value> = serde_json::from_slice(&content)?;
+        assert_eq!(output.rows, 3);
+        assert_eq!(rows[2]["Name"], "Giorgio Chiellini");
+        assert_eq!(rows[2]["Kit Number"], 3);
+        assert!(output.rejected.is_empty());
+        let lines = output.malformed.iter().map(|r| r.line).collect::<Vec<_>>();
+        assert_eq!(lines, vec![3, 5, 6]);
+        assert_eq!(
+            output.malformed[1].message,
+            "invalid UTF-8 in field 2, at byte 4"
+        );
+
+        let mut side = Vec::new();
+        process_csv_write_quarantine(&output.malformed, &dialect, &mut side)?;
+        let side = side.split(|b| *b == b'\n').collect::<Vec<_>>();
+        assert_eq!(side[0], b"input,line,message,record");
+        assert_eq!(
+            side[1],
+            b"fixtures/malformed.csv,3,\"expected 3 fields, found 2\",\"Mattia Perin,Goalkeeper\""
+        );
+        // the invalid byte is kept as it was read
+        assert!(side[2].ends_with(b"\"Carlo Pinsoglio,Goal\xffkeeper,31\""));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_markdown_html() -> anyhow::Result<()> {
+        let opts = CsvConvertOpts {
+            format: OutputFormat::Markdown,
+            columns: vec!["Name".into(), "Kit Number".into()],
+            limit: Some(1),
+            ..Default::default()
+        };
+        let (content, _) = convert("assets/juventus.csv", &CsvDialect::default(), &opts)?;
+        assert_eq!(
+            String::from_utf8(content)?,
+            "| Name | Kit Number |\n| --- | ---: |\n| Wojciech Szczesny | 1 |\n"
+        );
+
+        let opts = CsvConvertOpts {
+            format: OutputFormat::Html,
+            ..opts
+        };
+        let (content, _) = convert("assets/juventus.csv", &CsvDialect::default(), &opts)?;
+        let content = String::from_utf8(content)?;
+        assert!(content.starts_with("<!DOCTYPE html>\n"));
+        assert!(content.contains("<title>juventus.csv</title>"));
+        assert!(content.contains("  <td style=\"text-align: right\">1</td>\n"));
+        assert!(content.ends_with("</table>\n</body>\n</html>\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_inputs_union() -> anyhow::Result<()> {
+        let inputs = vec![
+            "fixtures/exports/2019-01.csv".to_owned(),
+            "fixtures/exports/2019-02.csv".to_owned(),
+        ];
+        let opts = CsvConvertOpts {
+            source_column: Some("Source".into()),
+            ..Default::default()
+        };
+        let mut content = Vec::new();
+        let output = process_csv(&inputs, &CsvDialect::default(), &opts, &mut content)?;
+        assert_eq!(output.rows, 3);
+        let rows: Vec<Map<String, Value>> = serde_json::from_slice(&content)?;
+        let keys = rows[0].keys().collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec!["Name", "Position", "Kit Number", "Nationality", "Source"]
+        );
+        assert_eq!(rows[0]["Nationality"], Value::Null);
+        assert_eq!(rows[0]["Source"], "fixtures/exports/2019-01.csv");
+        assert_eq!(rows[2]["Position"], Value::Null);
+        assert_eq!(rows[2]["Kit Number"], 77);
+        assert_eq!(rows[2]["Source"], "fixtures/exports/2019-02.csv");
+
+        let opts = CsvConvertOpts {
+            source_column: Some("Name".into()),
+            ..Default::default()
+        };
+        assert!(process_csv(&inputs, &CsvDialect::default(), &opts, &mut Vec::new()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_compressed() -> anyhow::Result<()> {
+        let opts = CsvConvertOpts {
+            format: OutputFormat::Csv,
+            ..Default::default()
+        };
+        let original = std::fs::read("assets/juventus.csv")?;
+        for ext in ["gz", "zst"] {
+            let path = std::env::temp_dir().join(format!("rcli_juventus.csv.{}", ext));
+            let path = path.to_string_lossy();
+            {
+                let mut writer = crate::create_output(&path)?;
+                process_csv(
+                    &["assets/juventus.csv".into()],
+                    &CsvDialect::default(),
+                    &opts,
+                    &mut writer,
+                )?;
+                writer.finish()?;
+            }
+            assert_ne!(std::fs::read(path.as_ref())?, original);
+            let (content, output) = convert(&path, &CsvDialect::default(), &opts)?;
+            assert_eq!(output.rows, 27);
+            assert_eq!(content, original);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,220 @@
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    io::Write,
+};
+
+use anyhow::anyhow;
+use csv::StringRecord;
+
+use crate::cli::Aggregate;
+
+use super::{
+    filter::compare_values, infer::is_null, reader::fit_record, table::render_table, CsvDialect,
+};
+
+#[derive(Debug, Clone)]
+pub struct PivotOpts {
+    // one output row per distinct combination of these columns
+    pub rows: Vec<String>,
+    // one output column per distinct value of this column
+    pub cols: String,
+    // aggregated column, count without it counts rows
+    pub value: Option<String>,
+    pub agg: Aggregate,
+}
+
+#[derive(Debug)]
+pub struct CsvPivot {
+    pub header: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Default)]
+struct Cell {
+    count: usize,
+    sum: f64,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+// null values are left out of every aggregate, as in sql; empty cells of the crosstab
+// are 0 for count and empty otherwise
+pub fn process_csv_pivot(
+    input: &str,
+    dialect: &CsvDialect,
+    opts: &PivotOpts,
+) -> anyhow::Result<CsvPivot> {
+    let mut reader = dialect.reader(input)?;
+    let mut header = dialect.headers(&mut reader)?;
+    let index = |column: &str| {
+        header
+            .iter()
+            .position(|h| h == column)
+            .ok_or(anyhow!("column {} does not exist", column))
+    };
+    if opts.rows.is_empty() {
+        return Err(anyhow!("at least one row column is required"));
+    }
+    let rows = opts
+        .rows
+        .iter()
+        .map(|c| index(c))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let col = index(&opts.cols)?;
+    let value = match (&opts.value, opts.agg) {
+        (Some(value), _) => Some(index(value)?),
+        (None, Aggregate::Count) => None,
+        (None, agg) => return Err(anyhow!("--agg {} needs a --value column", agg)),
+    };
+
+    let mut cells: HashMap<(Vec<String>, String), Cell> = HashMap::new();
+    let mut record = StringRecord::new();
+    while reader.read_record(&mut record)? {
+        fit_record(&mut header, &mut record);
+        let row_key = rows
+            .iter()
+            .map(|&i| record[i].to_owned())
+            .collect::<Vec<_>>();
+        let col_key = record[col].to_owned();
+        let cell = cells.entry((row_key, col_key)).or_default();
+        let Some(i) = value else {
+            cell.count += 1;
+            continue;
+        };
+        let value = &record[i];
+        if is_null(value) {
+            continue;
+        }
+        cell.count += 1;
+        if matches!(opts.agg, Aggregate::Count) {
+            continue;
+        }
+        let number = value.parse::<f64>().map_err(|_| {
+            let line = record.position().map(|p| p.line()).unwrap_or_default();
+            anyhow!("line {}: {:?} is not a number", line, value)
+        })?;
+        cell.sum += number;
+        cell.min = Some(cell.min.map_or(number, |m| m.min(number)));
+        cell.max = Some(cell.max.map_or(number, |m| m.max(number)));
+    }
+    let mut row_keys = Vec::from_iter(cells.keys().map(|(r, _)| r).collect::<HashSet<_>>());
+    let mut col_keys = Vec::from_iter(cells.keys().map(|(_, c)| c).collect::<HashSet<_>>());
+    row_keys.sort_by(|a, b| {
+        a.iter()
+            .zip(b.iter())
+            .map(|(a, b)| compare_keys(a, b))
+            .find(|o| o.is_ne())
+            .unwrap_or(Ordering::Equal)
+    });
+    col_keys.sort_by(|a, b| compare_keys(a, b));
+
+    let aggregate = |cell: Option<&Cell>| -> String {
+        let number = match (cell, opts.agg) {
+            (None, Aggregate::Count) => return "0".into(),
+            (None, _) => return String::new(),
+            (Some(cell), Aggregate::Count) => return cell.count.to_string(),
+            (Some(cell), _) if cell.count == 0 => return String::new(),
+            (Some(cell), Aggregate::Sum) => cell.sum,
+            (Some(cell), Aggregate::Avg) => cell.sum / cell.count as f64,
+            (Some(cell), Aggregate::Min) => cell.min.unwrap_or_default(),
+            (Some(cell), Aggregate::Max) => cell.max.unwrap_or_default(),
+        };
+        number.to_string()
+    };
+    let rows = row_keys
+        .iter()
+        .map(|&row_key| {
+            let values = col_keys
+                .iter()
+                .map(|&col_key| aggregate(cells.get(&(row_key.clone(), col_key.clone()))));
+            row_key.iter().cloned().chain(values).collect()
+        })
+        .collect();
+    Ok(CsvPivot {
+        header: opts.rows.iter().chain(col_keys).cloned().collect(),
+        rows,
+    })
+}
+
+// numbers first in numeric order, then everything else as text; "1" and "1.0" are told
+// apart by their text, so keys are in the same order on every run
+fn compare_keys(a: &str, b: &str) -> Ordering {
+    compare_values(a, b).then_with(|| a.cmp(b))
+}
+
+impl CsvPivot {
+    pub fn to_table(&self) -> String {
+        render_table(&self.header, &self.rows, 40, false)
+    }
+
+    pub fn write_csv(&self, output: &mut dyn Write) -> anyhow::Result<()> {
+        let mut writer = csv::Writer::from_writer(output);
+        writer.write_record(&self.header)?;
+        for row in self.rows.iter() {
+            writer.write_record(row)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pivot(agg: Aggregate, value: Option<&str>) -> anyhow::Result<CsvPivot> {
+        let opts = PivotOpts {
+            rows: vec!["Nationality".into()],
+            cols: "Position".into(),
+            value: value.map(String::from),
+            agg,
+        };
+        process_csv_pivot("assets/juventus.csv", &CsvDialect::default(), &opts)
+    }
+
+    fn cell<'a>(pivot: &'a CsvPivot, row: &str, col: &str) -> &'a str {
+        let i = pivot.header.iter().position(|h| h == col).unwrap();
+        let row = pivot.rows.iter().find(|r| r[0] == row).unwrap();
+        &row[i]
+    }
+
+    #[test]
+    fn test_process_csv_pivot() -> anyhow::Result<()> {
+        let count = pivot(Aggregate::Count, None)?;
+        assert_eq!(count.header[0], "Nationality");
+        assert_eq!(cell(&count, "Italy", "Goalkeeper"), "3");
+        assert_eq!(cell(&count, "Poland", "Centre-Forward"), "0");
+        let rows = count.rows.iter().map(|r| r[0].as_str()).collect::<Vec<_>>();
+        let mut sorted = rows.clone();
+        sorted.sort();
+        assert_eq!(rows, sorted);
+
+        let sum = pivot(Aggregate::Sum, Some("Kit Number"))?;
+        assert_eq!(cell(&sum, "Italy", "Goalkeeper"), "145");
+        assert_eq!(cell(&sum, "Poland", "Centre-Forward"), "");
+        let avg = pivot(Aggregate::Avg, Some("Kit Number"))?;
+        assert_eq!(cell(&avg, "Poland", "Goalkeeper"), "1");
+        let max = pivot(Aggregate::Max, Some("Kit Number"))?;
+        assert_eq!(cell(&max, "Italy", "Goalkeeper"), "77");
+
+        assert!(pivot(Aggregate::Sum, None).is_err());
+        assert!(pivot(Aggregate::Sum, Some("Name")).is_err());
+
+        let mut csv = Vec::new();
+        count.write_csv(&mut csv)?;
+        assert!(String::from_utf8(csv)?.starts_with("Nationality,"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_compare_keys_mixed() {
+        let mut keys = vec!["1a", "10", "b", "2", "1.0", "1", "-3"];
+        keys.sort_by(|a, b| compare_keys(a, b));
+        assert_eq!(keys, vec!["-3", "1", "1.0", "2", "10", "1a", "b"]);
+        // the same order whatever order the keys come in
+        keys.reverse();
+        keys.sort_by(|a, b| compare_keys(a, b));
+        assert_eq!(keys, vec!["-3", "1", "1.0", "2", "10", "1a", "b"]);
+    }
+}
@@ -0,0 +1,119 @@
+use std::{collections::HashSet, path::Path};
+
+use csv::StringRecord;
+
+// proto3 wire type for string / bytes / embedded messages
+const WIRE_TYPE_LEN: u64 = 2;
+
+// "assets/juventus.csv" -> "Juventus"
+pub fn message_name(input: &str) -> String {
+    let stem = Path::new(input)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default();
+    let name = stem
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            let mut chars = s.chars();
+            match chars.next() {
+                Some(c) => c.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect::<String>();
+    match name.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => name,
+        Some(_) => format!("Csv{}", name),
+        None => "Row".into(),
+    }
+}
+
+// "Kit Number" -> "kit_number", duplicated names get a numeric suffix
+pub fn field_names(header: &StringRecord) -> Vec<String> {
+    let mut seen = HashSet::new();
+    header
+        .iter()
+        .enumerate()
+        .map(|(i, h)| {
+            let mut name = h
+                .split(|c: char| !c.is_ascii_alphanumeric())
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_ascii_lowercase())
+                .collect::<Vec<_>>()
+                .join("_");
+            if name.is_empty() {
+                name = format!("field_{}", i + 1);
+            } else if name.starts_with(|c: char| c.is_ascii_digit()) {
+                name = format!("field_{}", name);
+            }
+            let mut unique = name.clone();
+            let mut n = 2;
+            while !seen.insert(unique.clone()) {
+                unique = format!("{}_{}", name, n);
+                n += 1;
+            }
+            unique
+        })
+        .collect()
+}
+
+pub fn schema(message: &str, header: &StringRecord) -> String {
+    let mut schema = String::from("syntax = \"proto3\";\n\n");
+    schema.push_str(&format!("message {} {{\n", message));
+    for (i, name) in field_names(header).iter().enumerate() {
+        schema.push_str(&format!("  string {} = {};\n", name, i + 1));
+    }
+    schema.push_str("}\n");
+    schema
+}
+
+// a row is written as varint(len) + message, same as `writeDelimitedTo` in protobuf-java
+pub fn encode_delimited<'a>(values: impl Iterator<Item = &'a str>, buf: &mut Vec<u8>) {
+    let mut message = Vec::new();
+    for (i, value) in values.enumerate() {
+        // proto3 does not serialize default values
+        if value.is_empty() {
+            continue;
+        }
+        encode_varint(((i as u64 + 1) << 3) | WIRE_TYPE_LEN, &mut message);
+        encode_varint(value.len() as u64, &mut message);
+        message.extend_from_slice(value.as_bytes());
+    }
+    encode_varint(message.len() as u64, buf);
+    buf.extend_from_slice(&message);
+}
+
+fn encode_varint(mut value: u64, buf: &mut Vec<u8>) {
+    while value >= 0x80 {
+        buf.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_and_field_names() {
+        assert_eq!(message_name("assets/juventus.csv"), "Juventus");
+        assert_eq!(
+            message_name("data/2024-roster_export.csv"),
+            "Csv2024RosterExport"
+        );
+        let header = StringRecord::from(vec!["Kit Number", "Name", "1st", "", "name"]);
+        assert_eq!(
+            field_names(&header),
+            vec!["kit_number", "name", "field_1st", "field_4", "name_2"]
+        );
+    }
+
+    #[test]
+    fn test_encode_varint() {
+        let mut buf = Vec::new();
+        encode_varint(300, &mut buf);
+        assert_eq!(buf, vec![0xac, 0x02]);
+    }
+}
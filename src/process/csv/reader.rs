@@ -0,0 +1,322 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{stdin, Read, Write},
+};
+
+use anyhow::anyhow;
+use csv::{ByteRecord, Reader, ReaderBuilder, StringRecord, Trim, WriterBuilder};
+use encoding_rs::Encoding;
+
+use crate::{cli::CsvTrim, util::decompress_reader};
+
+use super::encoding::decode_reader;
+
+#[derive(Debug, Clone)]
+pub struct CsvDialect {
+    pub delimiter: u8,
+    pub quote: u8,
+    // when set, `\"` style escapes are used instead of doubled quotes
+    pub escape: Option<u8>,
+    // lines starting with this byte are skipped
+    pub comment: Option<u8>,
+    pub trim: CsvTrim,
+    // allow records with a different number of fields than the header
+    pub flexible: bool,
+    // when false, headers are synthesized as col_1..col_n
+    pub has_headers: bool,
+    // input encoding, detected from a BOM or the first bytes when not set
+    pub encoding: Option<&'static Encoding>,
+}
+
+impl Default for CsvDialect {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            quote: b'"',
+            escape: None,
+            comment: None,
+            trim: CsvTrim::None,
+            flexible: false,
+            has_headers: true,
+            encoding: None,
+        }
+    }
+}
+
+impl From<CsvTrim> for Trim {
+    fn from(value: CsvTrim) -> Self {
+        match value {
+            CsvTrim::None => Trim::None,
+            CsvTrim::Headers => Trim::Headers,
+            CsvTrim::Fields => Trim::Fields,
+            CsvTrim::All => Trim::All,
+        }
+    }
+}
+
+impl CsvDialect {
+    // "-" reads from stdin, gzip and zstd input is decompressed and anything that
+    // is not utf-8 is transcoded first
+    pub fn reader(&self, input: &str) -> anyhow::Result<Reader<Box<dyn Read>>> {
+        let rdr: Box<dyn Read> = if input == "-" {
+            Box::new(stdin())
+        } else {
+            Box::new(File::open(input)?)
+        };
+        let rdr = decompress_reader(input, rdr)?;
+        Ok(self.reader_from(decode_reader(rdr, self.encoding)?))
+    }
+
+    pub fn reader_from<R: Read>(&self, rdr: R) -> Reader<R> {
+        ReaderBuilder::new()
+            .delimiter(self.delimiter)
+            .quote(self.quote)
+            .escape(self.escape)
+            .double_quote(self.escape.is_none())
+            .comment(self.comment)
+            .trim(self.trim.into())
+            .flexible(self.flexible)
+            .has_headers(self.has_headers)
+            .from_reader(rdr)
+    }
+
+    pub fn headers<R: Read>(&self, reader: &mut Reader<R>) -> anyhow::Result<StringRecord> {
+        let header = reader.headers()?;
+        if self.has_headers {
+            Ok(header.clone())
+        } else {
+            // without headers the csv crate returns the first record, which is still yielded by records()
+            Ok((1..=header.len()).map(column_name).collect())
+        }
+    }
+}
+
+pub type Records = Box<dyn Iterator<Item = anyhow::Result<StringRecord>>>;
+
+// one or more inputs read one after another as a single table
+pub struct CsvInputs<'a> {
+    pub paths: &'a [String],
+    pub dialect: &'a CsvDialect,
+    // extra last column holding the path each row was read from
+    pub source_column: Option<&'a str>,
+}
+
+impl CsvInputs<'_> {
+    pub fn is_stdin(&self) -> bool {
+        self.paths.iter().any(|p| p == "-")
+    }
+
+    // the header is the union of all headers in the order columns are first seen,
+    // cells a file does not have are left empty and so read as null;
+    // rows with the wrong number of fields or invalid utf-8 are a MalformedRow error
+    pub fn open(&self) -> anyhow::Result<(StringRecord, Records)> {
+        if self.paths.is_empty() {
+            return Err(anyhow!("no input"));
+        }
+        if self.paths.iter().filter(|p| *p == "-").count() > 1 {
+            return Err(anyhow!("stdin can only be read once"));
+        }
+        // field counts are checked here, so the row is still at hand when they do not match
+        let dialect = CsvDialect {
+            flexible: true,
+            ..self.dialect.clone()
+        };
+        let mut union: Vec<String> = Vec::new();
+        let mut readers = Vec::new();
+        for path in self.paths {
+            let mut reader = dialect.reader(path)?;
+            let header = self.dialect.headers(&mut reader)?;
+            let mut indices = Vec::new();
+            for (i, name) in header.iter().enumerate() {
+                // the n-th column of a name maps to the n-th column of that name in the union
+                let nth = header.iter().take(i).filter(|h| *h == name).count();
+                let index = match union
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, u)| *u == name)
+                    .nth(nth)
+                {
+                    Some((index, _)) => index,
+                    None => {
+                        union.push(name.to_owned());
+                        union.len() - 1
+                    }
+                };
+                indices.push(index);
+            }
+            readers.push((path.clone(), reader, indices));
+        }
+        let width = union.len();
+        if let Some(column) = self.source_column {
+            if union.iter().any(|h| h == column) {
+                return Err(anyhow!("source column {} already exists", column));
+            }
+            union.push(column.to_owned());
+        }
+
+        let single = readers.len() == 1 && self.source_column.is_none();
+        let source = self.source_column.is_some();
+        let flexible = self.dialect.flexible;
+        let records = readers
+            .into_iter()
+            .flat_map(move |(path, reader, indices)| {
+                reader.into_byte_records().map(move |result| {
+                    let record = result?;
+                    let malformed = |message| MalformedRow {
+                        input: path.clone(),
+                        line: record.position().map(|p| p.line()).unwrap_or_default(),
+                        message,
+                        record: record.clone(),
+                    };
+                    if !flexible && record.len() != indices.len() {
+                        let message =
+                            format!("expected {} fields, found {}", indices.len(), record.len());
+                        return Err(malformed(message).into());
+                    }
+                    let record = match StringRecord::from_byte_record(record.clone()) {
+                        Ok(record) => record,
+                        Err(e) => {
+                            let message = format!(
+                                "invalid UTF-8 in field {}, at byte {}",
+                                e.utf8_error().field() + 1,
+                                e.utf8_error().valid_up_to()
+                            );
+                            return Err(malformed(message).into());
+                        }
+                    };
+                    if single {
+                        return Ok(record);
+                    }
+                    let mut cells = vec![""; width];
+                    for (&index, value) in indices.iter().zip(record.iter()) {
+                        cells[index] = value;
+                    }
+                    if source {
+                        cells.push(&path);
+                    }
+                    // cells of flexible rows beyond the file's header come last
+                    cells.extend(record.iter().skip(indices.len()));
+                    let mut united = cells.into_iter().collect::<StringRecord>();
+                    united.set_position(record.position().cloned());
+                    Ok(united)
+                })
+            });
+        Ok((union.into_iter().collect(), Box::new(records)))
+    }
+}
+
+pub fn column_name(i: usize) -> String {
+    format!("col_{}", i)
+}
+
+// flexible records may be shorter or longer than the header:
+// short ones are padded with empty cells, extra cells get col_n names
+pub fn fit_record(header: &mut StringRecord, record: &mut StringRecord) {
+    while header.len() < record.len() {
+        header.push_field(&column_name(header.len() + 1));
+    }
+    while record.len() < header.len() {
+        record.push_field("");
+    }
+}
+
+// an error confined to one row, the reader carries on with the next one;
+// anything else, e.g. a failed read, still ends the conversion
+#[derive(Debug)]
+pub struct MalformedRow {
+    pub input: String,
+    pub line: u64,
+    pub message: String,
+    // the fields as they were read, which may not be utf-8
+    pub record: ByteRecord,
+}
+
+impl fmt::Display for MalformedRow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: line {}: {}", self.input, self.line, self.message)
+    }
+}
+
+impl std::error::Error for MalformedRow {}
+
+// input,line,message,record rows, the record is the row written back in the input dialect,
+// so it can be fixed and replayed under the input header
+pub fn process_csv_write_quarantine(
+    rows: &[MalformedRow],
+    dialect: &CsvDialect,
+    output: &mut dyn Write,
+) -> anyhow::Result<()> {
+    let mut writer = csv::Writer::from_writer(output);
+    writer.write_record(["input", "line", "message", "record"])?;
+    for row in rows {
+        let mut raw = WriterBuilder::new()
+            .delimiter(dialect.delimiter)
+            .quote(dialect.quote)
+            .flexible(true)
+            .from_writer(Vec::new());
+        raw.write_byte_record(&row.record)?;
+        let mut raw = raw.into_inner()?;
+        raw.pop();
+        let line = row.line.to_string();
+        writer.write_record([
+            row.input.as_bytes(),
+            line.as_bytes(),
+            row.message.as_bytes(),
+            &raw,
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dialect_tsv_without_headers() -> anyhow::Result<()> {
+        let dialect = CsvDialect {
+            delimiter: b'\t',
+            has_headers: false,
+            ..Default::default()
+        };
+        let mut reader = dialect.reader_from("a\t1\nb\t2\n".as_bytes());
+        let header = dialect.headers(&mut reader)?;
+        assert_eq!(header, vec!["col_1", "col_2"]);
+        let records = reader.records().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(records.len(), 2);
+        assert_eq!(&records[0][0], "a");
+        Ok(())
+    }
+
+    #[test]
+    fn test_dialect_escape_comment_trim() -> anyhow::Result<()> {
+        let dialect = CsvDialect {
+            delimiter: b';',
+            escape: Some(b'\\'),
+            comment: Some(b'#'),
+            trim: CsvTrim::All,
+            ..Default::default()
+        };
+        let data = "name ; note\n# skipped\nx ;\"say \\\"hi\\\"\"\n";
+        let mut reader = dialect.reader_from(data.as_bytes());
+        let header = dialect.headers(&mut reader)?;
+        assert_eq!(header, vec!["name", "note"]);
+        let records = reader.records().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(records.len(), 1);
+        assert_eq!(&records[0][1], "say \"hi\"");
+        Ok(())
+    }
+
+    #[test]
+    fn test_fit_record() {
+        let mut header = StringRecord::from(vec!["a", "b"]);
+        let mut short = StringRecord::from(vec!["1"]);
+        fit_record(&mut header, &mut short);
+        assert_eq!(short, vec!["1", ""]);
+        let mut long = StringRecord::from(vec!["1", "2", "3"]);
+        fit_record(&mut header, &mut long);
+        assert_eq!(header, vec!["a", "b", "col_3"]);
+    }
+}
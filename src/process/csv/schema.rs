@@ -0,0 +1,247 @@
+use std::{collections::HashMap, fmt};
+
+use anyhow::anyhow;
+use csv::StringRecord;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{cli::ColumnType, read_content};
+
+use super::{
+    infer::{is_null, is_type},
+    reader::fit_record,
+    CsvDialect,
+};
+
+// columns:
+//   - name: Kit Number
+//     required: true
+//     type: int
+//     unique: true
+//   - name: Position
+//     enum: [Goalkeeper, Centre-Back]
+#[derive(Debug, Clone, Deserialize)]
+pub struct CsvSchema {
+    pub columns: Vec<ColumnRule>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ColumnRule {
+    pub name: String,
+    // the column must be in the header
+    #[serde(default)]
+    pub required: bool,
+    // empty cells are allowed unless this is false
+    #[serde(default = "default_nullable")]
+    pub nullable: bool,
+    #[serde(rename = "type")]
+    pub ty: Option<ColumnType>,
+    // matched against the whole cell
+    pub pattern: Option<String>,
+    #[serde(rename = "enum")]
+    pub values: Option<Vec<String>>,
+    #[serde(default)]
+    pub unique: bool,
+}
+
+fn default_nullable() -> bool {
+    true
+}
+
+impl CsvSchema {
+    // yaml, which also reads json schema files
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let content = read_content(path)?;
+        Ok(serde_yaml::from_str(&content)?)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Violation {
+    pub line: u64,
+    pub column: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.column {
+            Some(column) => write!(f, "line {}, column {}: {}", self.line, column, self.message),
+            None => write!(f, "line {}: {}", self.line, self.message),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CompiledRule {
+    index: usize,
+    rule: ColumnRule,
+    pattern: Option<Regex>,
+    // value -> line it was first seen on, only for unique columns
+    seen: HashMap<String, u64>,
+}
+
+// checks rows one by one; it keeps the values of unique columns, so a clone
+// starts over with the same rules
+#[derive(Debug, Clone)]
+pub struct Validator {
+    rules: Vec<CompiledRule>,
+}
+
+impl Validator {
+    // missing required columns are reported against the header line
+    pub fn new(
+        schema: &CsvSchema,
+        header: &StringRecord,
+    ) -> anyhow::Result<(Self, Vec<Violation>)> {
+        let mut rules = Vec::new();
+        let mut violations = Vec::new();
+        for rule in schema.columns.iter() {
+            let Some(index) = header.iter().position(|h| h == rule.name) else {
+                if rule.required {
+                    violations.push(Violation {
+                        line: 1,
+                        column: Some(rule.name.clone()),
+                        message: "required column is missing".into(),
+                    });
+                }
+                continue;
+            };
+            let pattern = match &rule.pattern {
+                Some(pattern) => Some(
+                    Regex::new(&format!("^(?:{})$", pattern))
+                        .map_err(|e| anyhow!("column {}: invalid pattern: {}", rule.name, e))?,
+                ),
+                None => None,
+            };
+            rules.push(CompiledRule {
+                index,
+                rule: rule.clone(),
+                pattern,
+                seen: HashMap::new(),
+            });
+        }
+        Ok((Self { rules }, violations))
+    }
+
+    pub fn check(&mut self, record: &StringRecord) -> Vec<Violation> {
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        let mut violations = Vec::new();
+        for compiled in self.rules.iter_mut() {
+            let rule = &compiled.rule;
+            let value = record.get(compiled.index).unwrap_or_default();
+            let mut violation = |message: String| {
+                violations.push(Violation {
+                    line,
+                    column: Some(rule.name.clone()),
+                    message,
+                })
+            };
+            if is_null(value) {
+                if !rule.nullable {
+                    violation("value is required".into());
+                }
+                continue;
+            }
+            if let Some(ty) = rule.ty {
+                if !is_type(value, ty) {
+                    violation(format!("{:?} is not a valid {}", value, ty));
+                }
+            }
+            if let Some(pattern) = &compiled.pattern {
+                if !pattern.is_match(value) {
+                    violation(format!("{:?} does not match {}", value, pattern.as_str()));
+                }
+            }
+            if let Some(values) = &rule.values {
+                if !values.iter().any(|v| v == value) {
+                    violation(format!("{:?} is not one of {}", value, values.join(", ")));
+                }
+            }
+            if rule.unique {
+                if let Some(first) = compiled.seen.get(value) {
+                    violation(format!("{:?} is a duplicate of line {}", value, first));
+                } else {
+                    compiled.seen.insert(value.to_owned(), line);
+                }
+            }
+        }
+        violations
+    }
+
+    // declared types, used as conversion types unless overridden by --type
+    pub fn types(&self) -> impl Iterator<Item = (String, ColumnType)> + '_ {
+        self.rules
+            .iter()
+            .filter_map(|c| c.rule.ty.map(|ty| (c.rule.name.clone(), ty)))
+    }
+}
+
+// every violation is collected, including records the csv reader can not parse
+pub fn process_csv_validate(
+    input: &str,
+    dialect: &CsvDialect,
+    schema: &CsvSchema,
+) -> anyhow::Result<Vec<Violation>> {
+    let mut reader = dialect.reader(input)?;
+    let mut header = dialect.headers(&mut reader)?;
+    let (mut validator, mut violations) = Validator::new(schema, &header)?;
+    for result in reader.records() {
+        let mut record = match result {
+            Ok(record) => record,
+            Err(e) => {
+                violations.push(Violation {
+                    line: e.position().map(|p| p.line()).unwrap_or_default(),
+                    column: None,
+                    message: e.to_string(),
+                });
+                continue;
+            }
+        };
+        fit_record(&mut header, &mut record);
+        violations.extend(validator.check(&record));
+    }
+    Ok(violations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_csv_validate() -> anyhow::Result<()> {
+        let schema = CsvSchema::load("fixtures/juventus.schema.yaml")?;
+        let violations =
+            process_csv_validate("assets/juventus.csv", &CsvDialect::default(), &schema)?;
+        assert!(violations.is_empty(), "{:?}", violations);
+
+        let violations =
+            process_csv_validate("fixtures/invalid.csv", &CsvDialect::default(), &schema)?;
+        let messages = violations.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                r#"line 3, column Position: "Striker" is not one of Goalkeeper, Centre-Back, Left-Back, Right-Back, Defensive Midfield, Central Midfield, Attacking Midfield, Left Winger, Right Winger, Second Striker, Centre-Forward"#,
+                r#"line 4, column Name: value is required"#,
+                r#"line 4, column Kit Number: "ten" is not a valid int"#,
+                r#"line 5, column Kit Number: "1" is a duplicate of line 2"#,
+                r#"line 6: CSV error: record 5 (line: 6, byte: 251): found record with 2 fields, but the previous record has 5 fields"#,
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_validator_required_column() -> anyhow::Result<()> {
+        let schema: CsvSchema =
+            serde_yaml::from_str("columns: [{name: Club, required: true}, {name: Caps}]")?;
+        let header = StringRecord::from(vec!["Name"]);
+        let (_, violations) = Validator::new(&schema, &header)?;
+        assert_eq!(violations.len(), 1);
+        assert_eq!(
+            violations[0].to_string(),
+            "line 1, column Club: required column is missing"
+        );
+        Ok(())
+    }
+}
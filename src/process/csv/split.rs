@@ -0,0 +1,587 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use anyhow::anyhow;
+use csv::StringRecord;
+use encoding_rs::{UTF_16BE, UTF_16LE};
+
+use crate::util::{compress_writer, OutputWriter};
+
+use super::{
+    check_unique, proto_schema,
+    reader::{fit_record, CsvInputs},
+    row_writer, scan_types, write_record, ColumnTypes, CsvConvertOpts, CsvDialect, Masker,
+    Projection, RowWriter, Transformer,
+};
+
+// parts open at once, well below the usual limit of open files; splitting by a column with
+// more values closes the least recently used part and appends to it when it gets rows again
+const MAX_OPEN_PARTS: usize = 64;
+
+#[derive(Debug, Clone)]
+pub enum SplitBy {
+    // one file per distinct value of the column
+    Column(String),
+    // files of at most this many rows
+    Rows(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct SplitOpts {
+    pub by: SplitBy,
+    // file names with {stem}, {value}, {index} and {ext} placeholders,
+    // defaults to "{stem}_{value}.{ext}" or "{stem}_{index}.{ext}"
+    pub template: Option<String>,
+    pub output_dir: String,
+}
+
+#[derive(Debug)]
+pub struct SplitFile {
+    pub path: PathBuf,
+    pub rows: usize,
+}
+
+struct Part {
+    path: PathBuf,
+    // None while the part is closed
+    writer: Option<(Box<dyn RowWriter>, PartOutput)>,
+    // whether the file has been written, so opening it again appends
+    created: bool,
+    rows: usize,
+    // rows of a part that could not be opened and can not be appended to later,
+    // written once the open parts are done
+    pending: Vec<StringRecord>,
+    last_used: usize,
+}
+
+// the row writer writes to the file, the part finishes it once the row writer is done
+#[derive(Clone)]
+struct PartOutput(Rc<RefCell<OutputWriter<BufWriter<File>>>>);
+
+impl Write for PartOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+// every file gets the whole header and the types inferred over the whole input,
+// so the parts of a split convert back the same way
+pub fn process_csv_split(
+    input: &str,
+    dialect: &CsvDialect,
+    split: &SplitOpts,
+    opts: &CsvConvertOpts,
+) -> anyhow::Result<Vec<SplitFile>> {
+    let paths = [input.to_owned()];
+    let source = CsvInputs {
+        paths: &paths,
+        dialect,
+        source_column: None,
+    };
+    let (input_header, mut records) = source.open()?;
+    let transformer = Transformer::new(&input_header, &opts.transforms)?;
+    let mut header = transformer.header();
+    let column = match &split.by {
+        SplitBy::Column(column) => Some(
+            header
+                .iter()
+                .position(|h| h == column)
+                .ok_or(anyhow!("column {} does not exist", column))?,
+        ),
+        SplitBy::Rows(0) => return Err(anyhow!("--rows must be greater than 0")),
+        SplitBy::Rows(_) => None,
+    };
+    if !opts.group_by.is_empty() {
+        return Err(anyhow!("--group-by can not be used with split"));
+    }
+    if opts.sqlite_db.is_some() {
+        return Err(anyhow!("--sqlite-db can not be used with split"));
+    }
+    let projection = Projection::new(&header, &[])?;
+    let infer = opts.should_infer();
+    let (inference, sampled) = if infer {
+        scan_types(
+            &source,
+            &mut records,
+            &transformer,
+            &mut header,
+            &projection,
+            |_| true,
+        )?
+    } else {
+        Default::default()
+    };
+    check_unique(&header)?;
+    let mut types = ColumnTypes::new(&inference, &header, opts, &opts.types)?;
+    let masker = Masker::new(&header, &opts.masks, opts.mask_key.as_deref())?;
+    masker.mask_types(&mut types.types, infer);
+
+    let stem = match input {
+        "-" => "stdin",
+        input => Path::new(input)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("output"),
+    };
+    let ext = opts.format.extension();
+    let template = match (&split.template, column) {
+        (Some(template), _) => template.as_str(),
+        (None, Some(_)) => "{stem}_{value}.{ext}",
+        (None, None) => "{stem}_{index}.{ext}",
+    };
+    fs::create_dir_all(&split.output_dir)?;
+
+    let mut parts = Parts {
+        dir: &split.output_dir,
+        opts,
+        input,
+        projection: &projection,
+        masker: &masker,
+        // a second utf-16 BOM would end up in the middle of the file
+        appendable: opts.format.appendable()
+            && opts
+                .output_encoding
+                .is_none_or(|e| e != UTF_16LE && e != UTF_16BE),
+        parts: Vec::new(),
+        paths: HashSet::new(),
+        open: 0,
+        clock: 0,
+    };
+    let mut by_value = HashMap::new();
+    let records = records.map(|r| transformer.apply(r?));
+    let run = || -> anyhow::Result<()> {
+        for result in sampled.into_iter().chain(records) {
+            let mut record = result?;
+            fit_record(&mut header, &mut record);
+            let part = match column {
+                Some(i) => {
+                    let value = record.get(i).unwrap_or_default();
+                    match by_value.get(value) {
+                        Some(&part) => part,
+                        None => {
+                            let index = parts.parts.len() + 1;
+                            let part = parts.add(file_name(template, stem, value, index, ext))?;
+                            by_value.insert(value.to_owned(), part);
+                            part
+                        }
+                    }
+                }
+                None => match parts.parts.last() {
+                    Some(part) if matches!(split.by, SplitBy::Rows(n) if part.rows < n) => {
+                        parts.parts.len() - 1
+                    }
+                    _ => {
+                        if let Some(last) = parts.parts.len().checked_sub(1) {
+                            // a full part is done, so only one file is open at a time
+                            parts.close(last)?;
+                        }
+                        let index = parts.parts.len() + 1;
+                        let name = file_name(template, stem, &index.to_string(), index, ext);
+                        parts.add(name)?
+                    }
+                },
+            };
+            parts.write(part, &header, &mut types, record)?;
+        }
+        parts.finish(&header, &mut types)
+    };
+    if let Err(e) = run() {
+        parts.remove_files();
+        return Err(e);
+    }
+
+    if let Some(schema) = proto_schema(opts, input, &header) {
+        fs::write(
+            Path::new(&split.output_dir).join(format!("{}.proto", stem)),
+            schema,
+        )?;
+    }
+    Ok(parts
+        .parts
+        .into_iter()
+        .map(|part| SplitFile {
+            path: part.path,
+            rows: part.rows,
+        })
+        .collect())
+}
+
+struct Parts<'a> {
+    dir: &'a str,
+    opts: &'a CsvConvertOpts,
+    input: &'a str,
+    projection: &'a Projection,
+    masker: &'a Masker,
+    appendable: bool,
+    parts: Vec<Part>,
+    paths: HashSet<PathBuf>,
+    open: usize,
+    // bumped on every write, for finding the least recently used part
+    clock: usize,
+}
+
+impl Parts<'_> {
+    // no file is written yet, but the name is already taken
+    fn add(&mut self, name: String) -> anyhow::Result<usize> {
+        let path = Path::new(self.dir).join(name);
+        // e.g. "a/b" and "a:b" are both written as a_b, or the template has no placeholder
+        if !self.paths.insert(path.clone()) {
+            return Err(anyhow!(
+                "more than one part would be written to {}",
+                path.display()
+            ));
+        }
+        self.parts.push(Part {
+            path,
+            writer: None,
+            created: false,
+            rows: 0,
+            pending: Vec::new(),
+            last_used: 0,
+        });
+        Ok(self.parts.len() - 1)
+    }
+
+    fn write(
+        &mut self,
+        i: usize,
+        header: &StringRecord,
+        types: &mut ColumnTypes,
+        record: StringRecord,
+    ) -> anyhow::Result<()> {
+        self.parts[i].rows += 1;
+        if !self.open_part(i, header, types)? {
+            self.parts[i].pending.push(record);
+            return Ok(());
+        }
+        let (writer, _) = self.parts[i].writer.as_mut().expect("the part is open");
+        write_record(
+            &mut **writer,
+            self.projection,
+            self.masker,
+            header,
+            types,
+            &record,
+        )
+    }
+
+    // false when too many parts are open and this one could not be appended to later
+    fn open_part(
+        &mut self,
+        i: usize,
+        header: &StringRecord,
+        types: &ColumnTypes,
+    ) -> anyhow::Result<bool> {
+        self.clock += 1;
+        self.parts[i].last_used = self.clock;
+        if self.parts[i].writer.is_some() {
+            return Ok(true);
+        }
+        if self.open >= MAX_OPEN_PARTS {
+            if !self.appendable {
+                return Ok(false);
+            }
+            let lru = (0..self.parts.len())
+                .filter(|&j| self.parts[j].writer.is_some())
+                .min_by_key(|&j| self.parts[j].last_used)
+                .expect("parts are open");
+            self.close(lru)?;
+        }
+        let part = &mut self.parts[i];
+        let resumed = part.created;
+        let file = if resumed {
+            OpenOptions::new().append(true).open(&part.path)?
+        } else {
+            File::create(&part.path)?
+        };
+        part.created = true;
+        let path = part.path.to_string_lossy();
+        // each reopening adds a gzip member or zstd frame, which read back as one stream
+        let output = PartOutput(Rc::new(RefCell::new(compress_writer(
+            &path,
+            BufWriter::new(file),
+        )?)));
+        let mut writer = row_writer(self.opts, self.input, output.clone())?;
+        if resumed {
+            writer.resume(header, &types.types)?;
+        } else {
+            writer.begin(header, &types.types)?;
+        }
+        part.writer = Some((writer, output));
+        self.open += 1;
+        Ok(true)
+    }
+
+    fn close(&mut self, i: usize) -> anyhow::Result<()> {
+        if let Some((mut writer, output)) = self.parts[i].writer.take() {
+            self.open -= 1;
+            writer.finish()?;
+            drop(writer);
+            output.0.borrow_mut().finish()?;
+        }
+        Ok(())
+    }
+
+    // held back rows are written one part at a time once every open part is closed
+    fn finish(&mut self, header: &StringRecord, types: &mut ColumnTypes) -> anyhow::Result<()> {
+        for i in 0..self.parts.len() {
+            self.close(i)?;
+        }
+        for i in 0..self.parts.len() {
+            let pending = std::mem::take(&mut self.parts[i].pending);
+            if pending.is_empty() {
+                continue;
+            }
+            self.parts[i].rows -= pending.len();
+            for record in pending {
+                self.write(i, header, types, record)?;
+            }
+            self.close(i)?;
+        }
+        Ok(())
+    }
+
+    // a failed split leaves no partial output behind
+    fn remove_files(&mut self) {
+        for part in self.parts.iter_mut() {
+            drop(part.writer.take());
+            if part.created {
+                let _ = fs::remove_file(&part.path);
+            }
+        }
+    }
+}
+
+fn file_name(template: &str, stem: &str, value: &str, index: usize, ext: &str) -> String {
+    template
+        .replace("{stem}", stem)
+        .replace("{value}", &sanitize(value))
+        .replace("{index}", &index.to_string())
+        .replace("{ext}", ext)
+}
+
+// cell values become part of a file name, so anything but letters, digits, '-' and '.'
+// is replaced; empty cells get a name no value is sanitized to, '@' is always replaced
+fn sanitize(value: &str) -> String {
+    let name = value
+        .trim()
+        .chars()
+        .map(|c| match c {
+            c if c.is_alphanumeric() || matches!(c, '-' | '.') => c,
+            _ => '_',
+        })
+        .collect::<String>();
+    if name.is_empty() {
+        return "@empty".into();
+    }
+    // "." and ".." are not names of their own
+    match name.trim_matches('.') {
+        "" => name.replace('.', "_"),
+        _ => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::OutputFormat;
+    use std::io::Read;
+
+    fn split_dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("rcli_split_{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        dir.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_process_csv_split_by_column() -> anyhow::Result<()> {
+        let split = SplitOpts {
+            by: SplitBy::Column("Nationality".into()),
+            template: None,
+            output_dir: split_dir("by_column"),
+        };
+        let opts = CsvConvertOpts {
+            format: OutputFormat::Csv,
+            ..Default::default()
+        };
+        let files =
+            process_csv_split("assets/juventus.csv", &CsvDialect::default(), &split, &opts)?;
+        assert_eq!(files.iter().map(|f| f.rows).sum::<usize>(), 27);
+        let italy = files
+            .iter()
+            .find(|f| f.path.ends_with("juventus_Italy.csv"))
+            .expect("a file for Italy");
+        let content = fs::read_to_string(&italy.path)?;
+        let mut lines = content.lines();
+        assert_eq!(
+            lines.next(),
+            Some("Name,Position,DOB,Nationality,Kit Number")
+        );
+        assert_eq!(lines.count(), italy.rows);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_split_by_rows() -> anyhow::Result<()> {
+        let split = SplitOpts {
+            by: SplitBy::Rows(10),
+            template: Some("part-{index}.{ext}".into()),
+            output_dir: split_dir("by_rows"),
+        };
+        let files = process_csv_split(
+            "assets/juventus.csv",
+            &CsvDialect::default(),
+            &split,
+            &Default::default(),
+        )?;
+        let rows = files.iter().map(|f| f.rows).collect::<Vec<_>>();
+        assert_eq!(rows, vec![10, 10, 7]);
+        assert!(files[2].path.ends_with("part-3.json"));
+        let last: Vec<serde_json::Value> = serde_json::from_slice(&fs::read(&files[2].path)?)?;
+        assert_eq!(last.len(), 7);
+        assert!(last[0]["Kit Number"].is_number());
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_split_collision() {
+        let split = SplitOpts {
+            by: SplitBy::Column("Nationality".into()),
+            template: Some("{stem}.{ext}".into()),
+            output_dir: split_dir("collision"),
+        };
+        let result = process_csv_split(
+            "assets/juventus.csv",
+            &CsvDialect::default(),
+            &split,
+            &Default::default(),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_process_csv_split_empty_values() -> anyhow::Result<()> {
+        let input = std::env::temp_dir().join("rcli_split_empty.csv");
+        fs::write(&input, "id,status\n1,\n2,empty\n3,\n")?;
+        let split = SplitOpts {
+            by: SplitBy::Column("status".into()),
+            template: Some("{value}.{ext}".into()),
+            output_dir: split_dir("empty"),
+        };
+        let opts = CsvConvertOpts {
+            format: OutputFormat::Csv,
+            ..Default::default()
+        };
+        let files = process_csv_split(
+            &input.to_string_lossy(),
+            &CsvDialect::default(),
+            &split,
+            &opts,
+        )?;
+        let parts = files
+            .iter()
+            .map(|f| {
+                (
+                    f.path.file_name().unwrap().to_string_lossy().into_owned(),
+                    f.rows,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            parts,
+            vec![("@empty.csv".to_owned(), 2), ("empty.csv".to_owned(), 1)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_split_more_values_than_open_parts() -> anyhow::Result<()> {
+        let input = std::env::temp_dir().join("rcli_split_many.csv");
+        let mut content = String::from("id,round\n");
+        for round in 0..2 {
+            for id in 0..MAX_OPEN_PARTS + 10 {
+                content.push_str(&format!("v{},{}\n", id, round));
+            }
+        }
+        fs::write(&input, content)?;
+        let input = input.to_string_lossy();
+        let formats = [
+            (OutputFormat::Csv, "{value}.{ext}.gz"),
+            (OutputFormat::Sql, "{value}.{ext}"),
+            (OutputFormat::Json, "{value}.{ext}"),
+        ];
+        for (format, template) in formats {
+            let split = SplitOpts {
+                by: SplitBy::Column("id".into()),
+                template: Some(template.into()),
+                output_dir: split_dir(&format!("many_{}", format)),
+            };
+            let opts = CsvConvertOpts {
+                format,
+                ..Default::default()
+            };
+            let files = process_csv_split(&input, &CsvDialect::default(), &split, &opts)?;
+            assert_eq!(files.len(), MAX_OPEN_PARTS + 10);
+            assert!(files.iter().all(|f| f.rows == 2));
+
+            let mut content = String::new();
+            let file = Box::new(File::open(&files[0].path)?);
+            crate::util::decompress_reader(&files[0].path.to_string_lossy(), file)?
+                .read_to_string(&mut content)?;
+            match format {
+                OutputFormat::Csv => assert_eq!(content, "id,round\nv0,0\nv0,1\n"),
+                OutputFormat::Sql => {
+                    assert_eq!(content.matches("CREATE TABLE").count(), 1);
+                    assert_eq!(content.matches("INSERT INTO").count(), 2);
+                }
+                _ => {
+                    let rows: Vec<serde_json::Value> = serde_json::from_str(&content)?;
+                    assert_eq!(rows.len(), 2);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_split_error_removes_parts() -> anyhow::Result<()> {
+        let input = std::env::temp_dir().join("rcli_split_collision.csv");
+        fs::write(&input, "id\nx\na/b\na:b\n")?;
+        let dir = split_dir("cleanup");
+        let split = SplitOpts {
+            by: SplitBy::Column("id".into()),
+            template: None,
+            output_dir: dir.clone(),
+        };
+        let result = process_csv_split(
+            &input.to_string_lossy(),
+            &CsvDialect::default(),
+            &split,
+            &Default::default(),
+        );
+        assert!(result.is_err());
+        assert_eq!(fs::read_dir(&dir)?.count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize("Côte d'Ivoire"), "Côte_d_Ivoire");
+        assert_eq!(sanitize("../etc"), ".._etc");
+        assert_eq!(sanitize(""), "@empty");
+        assert_eq!(sanitize(" "), "@empty");
+        assert_eq!(sanitize("empty"), "empty");
+        assert_eq!(sanitize("@empty"), "_empty");
+        assert_eq!(sanitize(".."), "__");
+    }
+}
@@ -0,0 +1,265 @@
+use std::{io::Write, path::Path};
+
+use anyhow::anyhow;
+use csv::StringRecord;
+use rusqlite::{params_from_iter, types::Value as SqlValue, Connection};
+use serde_json::Value;
+
+use crate::cli::{ColumnType, SqlDialect};
+
+use super::writer::{Row, RowWriter};
+
+// CREATE TABLE once the column types are known, then one INSERT per batch of rows
+pub struct SqlWriter<'a> {
+    pub w: Box<dyn Write + 'a>,
+    pub dialect: SqlDialect,
+    pub table: String,
+    pub batch: usize,
+    pub columns: Vec<String>,
+    pub rows: Vec<String>,
+}
+
+impl SqlWriter<'_> {
+    fn flush_rows(&mut self) -> anyhow::Result<()> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+        writeln!(
+            self.w,
+            "INSERT INTO {} ({}) VALUES\n  {};",
+            quote_ident(&self.table, self.dialect),
+            self.columns.join(", "),
+            self.rows.join(",\n  ")
+        )?;
+        self.rows.clear();
+        Ok(())
+    }
+}
+
+impl RowWriter for SqlWriter<'_> {
+    fn begin(&mut self, header: &StringRecord, types: &[Option<ColumnType>]) -> anyhow::Result<()> {
+        self.resume(header, types)?;
+        let columns = self
+            .columns
+            .iter()
+            .zip(types)
+            .map(|(name, ty)| format!("  {} {}", name, column_type(*ty, self.dialect)))
+            .collect::<Vec<_>>();
+        writeln!(
+            self.w,
+            "CREATE TABLE {} (\n{}\n);",
+            quote_ident(&self.table, self.dialect),
+            columns.join(",\n")
+        )?;
+        Ok(())
+    }
+
+    // the table exists, only the INSERT columns are needed
+    fn resume(&mut self, header: &StringRecord, _: &[Option<ColumnType>]) -> anyhow::Result<()> {
+        self.columns = header
+            .iter()
+            .map(|h| quote_ident(h, self.dialect))
+            .collect();
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &Row) -> anyhow::Result<()> {
+        check_width(row, self.columns.len())?;
+        let values = row
+            .values()
+            .map(|v| literal(v, self.dialect))
+            .collect::<Vec<_>>();
+        self.rows.push(format!("({})", values.join(", ")));
+        if self.rows.len() >= self.batch {
+            self.flush_rows()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.flush_rows()?;
+        self.w.flush()?;
+        Ok(())
+    }
+}
+
+// rows are inserted in one transaction, which is committed by finish
+pub struct SqliteWriter {
+    conn: Connection,
+    table: String,
+    insert: String,
+    width: usize,
+}
+
+impl SqliteWriter {
+    pub fn open(path: &str, table: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            conn: Connection::open(path)?,
+            table: table.to_owned(),
+            insert: String::new(),
+            width: 0,
+        })
+    }
+}
+
+impl RowWriter for SqliteWriter {
+    fn begin(&mut self, header: &StringRecord, types: &[Option<ColumnType>]) -> anyhow::Result<()> {
+        let dialect = SqlDialect::Sqlite;
+        let table = quote_ident(&self.table, dialect);
+        let columns = header
+            .iter()
+            .map(|h| quote_ident(h, dialect))
+            .collect::<Vec<_>>();
+        let definitions = columns
+            .iter()
+            .zip(types)
+            .map(|(name, ty)| format!("{} {}", name, column_type(*ty, dialect)))
+            .collect::<Vec<_>>();
+        self.conn.execute_batch(&format!(
+            "BEGIN; CREATE TABLE {} ({});",
+            table,
+            definitions.join(", ")
+        ))?;
+        self.width = columns.len();
+        let placeholders = vec!["?"; columns.len()].join(", ");
+        self.insert = format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            table,
+            columns.join(", "),
+            placeholders
+        );
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &Row) -> anyhow::Result<()> {
+        if self.insert.is_empty() {
+            return Err(anyhow!("the table has not been created"));
+        }
+        check_width(row, self.width)?;
+        let values = row.values().map(|v| match v {
+            Value::Null => SqlValue::Null,
+            Value::Bool(b) => SqlValue::Integer(*b as i64),
+            Value::Number(n) => match n.as_i64() {
+                Some(i) => SqlValue::Integer(i),
+                None => SqlValue::Real(n.as_f64().unwrap_or_default()),
+            },
+            Value::String(s) => SqlValue::Text(s.clone()),
+            v => SqlValue::Text(v.to_string()),
+        });
+        self.conn
+            .prepare_cached(&self.insert)?
+            .execute(params_from_iter(values))?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        if !self.insert.is_empty() {
+            self.conn.execute_batch("COMMIT;")?;
+        }
+        Ok(())
+    }
+}
+
+// "assets/juventus.csv" -> "juventus", stdin -> "rows"
+pub fn table_name(input: &str) -> String {
+    let stem = Path::new(input)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .filter(|_| input != "-")
+        .unwrap_or("rows");
+    stem.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn quote_ident(name: &str, dialect: SqlDialect) -> String {
+    match dialect {
+        SqlDialect::Mysql => format!("`{}`", name.replace('`', "``")),
+        _ => format!("\"{}\"", name.replace('"', "\"\"")),
+    }
+}
+
+// every row has a value for each column of the table;
+// columns only seen in later flexible rows are not part of it
+fn check_width(row: &Row, width: usize) -> anyhow::Result<()> {
+    if row.len() != width {
+        return Err(anyhow!(
+            "row has {} columns, the table has {}",
+            row.len(),
+            width
+        ));
+    }
+    Ok(())
+}
+
+// sqlite has no date types, dates are stored as ISO 8601 text
+fn column_type(ty: Option<ColumnType>, dialect: SqlDialect) -> &'static str {
+    match (ty, dialect) {
+        (Some(ColumnType::Int), SqlDialect::Sqlite) => "INTEGER",
+        (Some(ColumnType::Int), _) => "BIGINT",
+        (Some(ColumnType::Float), SqlDialect::Sqlite) => "REAL",
+        (Some(ColumnType::Float), SqlDialect::Postgres) => "DOUBLE PRECISION",
+        (Some(ColumnType::Float), SqlDialect::Mysql) => "DOUBLE",
+        (Some(ColumnType::Bool), SqlDialect::Sqlite) => "INTEGER",
+        (Some(ColumnType::Bool), _) => "BOOLEAN",
+        (Some(ColumnType::Date), SqlDialect::Sqlite) => "TEXT",
+        (Some(ColumnType::Date), _) => "DATE",
+        (Some(ColumnType::DateTime), SqlDialect::Sqlite) => "TEXT",
+        (Some(ColumnType::DateTime), SqlDialect::Postgres) => "TIMESTAMP",
+        (Some(ColumnType::DateTime), SqlDialect::Mysql) => "DATETIME",
+        (Some(ColumnType::String), _) | (None, _) => "TEXT",
+    }
+}
+
+// mysql also treats backslashes in strings as escapes unless NO_BACKSLASH_ESCAPES is set
+fn literal(value: &Value, dialect: SqlDialect) -> String {
+    match value {
+        Value::Null => "NULL".into(),
+        Value::Bool(b) => match dialect {
+            SqlDialect::Sqlite => (*b as u8).to_string(),
+            _ => if *b { "TRUE" } else { "FALSE" }.into(),
+        },
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => {
+            let escaped = match dialect {
+                SqlDialect::Mysql => s.replace('\\', "\\\\").replace('\'', "''"),
+                _ => s.replace('\'', "''"),
+            };
+            format!("'{}'", escaped)
+        }
+        v => format!("'{}'", v.to_string().replace('\'', "''")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_literal() {
+        let value = Value::from("it's C:\\");
+        assert_eq!(literal(&value, SqlDialect::Postgres), "'it''s C:\\'");
+        assert_eq!(literal(&value, SqlDialect::Mysql), "'it''s C:\\\\'");
+        assert_eq!(literal(&Value::Bool(true), SqlDialect::Sqlite), "1");
+        assert_eq!(literal(&Value::Null, SqlDialect::Mysql), "NULL");
+        assert_eq!(
+            quote_ident("Kit \"No\"", SqlDialect::Sqlite),
+            "\"Kit \"\"No\"\"\""
+        );
+        assert_eq!(quote_ident("Kit Number", SqlDialect::Mysql), "`Kit Number`");
+    }
+
+    #[test]
+    fn test_check_width() {
+        let row = Row::from_iter([("a".into(), Value::from(1)), ("b".into(), Value::from(2))]);
+        assert!(check_width(&row, 2).is_ok());
+        assert!(check_width(&row, 1).is_err());
+        assert!(check_width(&row, 3).is_err());
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::cli::ColumnType;
+
+use super::{
+    infer::{is_null, TypeInference},
+    reader::fit_record,
+    table::render_table,
+    CsvDialect,
+};
+
+#[derive(Debug, Serialize)]
+pub struct CsvStats {
+    pub rows: usize,
+    pub columns: Vec<ColumnStats>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ColumnStats {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: ColumnType,
+    // null and empty cells
+    pub nulls: usize,
+    pub distinct: usize,
+    // numbers for numeric columns, otherwise the lexicographically smallest / largest text
+    pub min: Option<Value>,
+    pub max: Option<Value>,
+    pub mean: Option<f64>,
+    pub median: Option<f64>,
+    pub top: Vec<TopValue>,
+    // in characters, not bytes
+    pub max_length: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TopValue {
+    pub value: String,
+    pub count: usize,
+}
+
+// cell counts per column, which is all that is needed for every statistic
+#[derive(Debug, Default)]
+struct ColumnCounts {
+    nulls: usize,
+    values: HashMap<String, usize>,
+}
+
+pub fn process_csv_stats(
+    input: &str,
+    dialect: &CsvDialect,
+    top: usize,
+) -> anyhow::Result<CsvStats> {
+    let mut reader = dialect.reader(input)?;
+    let mut header = dialect.headers(&mut reader)?;
+    let mut inference = TypeInference::default();
+    let mut counts: Vec<ColumnCounts> = Vec::new();
+    let mut rows = 0;
+    for result in reader.records() {
+        let mut record = result?;
+        fit_record(&mut header, &mut record);
+        inference.update(&record);
+        counts.resize_with(header.len(), Default::default);
+        for (count, value) in counts.iter_mut().zip(record.iter()) {
+            if is_null(value) {
+                count.nulls += 1;
+            } else {
+                *count.values.entry(value.to_owned()).or_default() += 1;
+            }
+        }
+        rows += 1;
+    }
+    counts.resize_with(header.len(), Default::default);
+    let types = inference.types(&header, true, &[])?;
+
+    let columns = header
+        .iter()
+        .zip(counts)
+        .zip(types)
+        .map(|((name, counts), ty)| {
+            column_stats(name, counts, ty.unwrap_or(ColumnType::String), top)
+        })
+        .collect();
+    Ok(CsvStats { rows, columns })
+}
+
+fn column_stats(name: &str, counts: ColumnCounts, ty: ColumnType, top: usize) -> ColumnStats {
+    let max_length = counts
+        .values
+        .keys()
+        .map(|v| v.chars().count())
+        .max()
+        .unwrap_or_default();
+    let mut frequent = counts.values.iter().collect::<Vec<_>>();
+    // ties are broken by value so the report is stable between runs
+    frequent.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    let top = frequent
+        .into_iter()
+        .take(top)
+        .map(|(value, count)| TopValue {
+            value: value.clone(),
+            count: *count,
+        })
+        .collect();
+
+    let (mut min, mut max, mut mean, mut median) = (None, None, None, None);
+    if matches!(ty, ColumnType::Int | ColumnType::Float) {
+        let mut numbers = counts
+            .values
+            .iter()
+            .filter_map(|(v, c)| v.parse::<f64>().ok().map(|n| (n, *c)))
+            .collect::<Vec<_>>();
+        numbers.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let total = numbers.iter().map(|(_, c)| c).sum::<usize>();
+        if total > 0 {
+            if ty == ColumnType::Int {
+                // f64 loses precision above 2^53, e.g. for ids and phone numbers
+                let ints = counts.values.keys().filter_map(|v| v.parse::<i64>().ok());
+                min = ints.clone().min().map(Value::from);
+                max = ints.max().map(Value::from);
+            } else {
+                min = numbers.first().map(|(n, _)| Value::from(*n));
+                max = numbers.last().map(|(n, _)| Value::from(*n));
+            }
+            mean = Some(numbers.iter().map(|(n, c)| n * *c as f64).sum::<f64>() / total as f64);
+            median = Some(median_of(&numbers, total));
+        }
+    } else {
+        min = counts.values.keys().min().map(|v| Value::from(v.as_str()));
+        max = counts.values.keys().max().map(|v| Value::from(v.as_str()));
+    }
+
+    ColumnStats {
+        name: name.to_owned(),
+        ty,
+        nulls: counts.nulls,
+        distinct: counts.values.len(),
+        min,
+        max,
+        mean,
+        median,
+        top,
+        max_length,
+    }
+}
+
+// numbers are sorted distinct values with their counts, total is the sum of the counts
+fn median_of(numbers: &[(f64, usize)], total: usize) -> f64 {
+    let nth = |n: usize| {
+        let mut seen = 0;
+        for (value, count) in numbers {
+            seen += count;
+            if seen > n {
+                return *value;
+            }
+        }
+        f64::NAN
+    };
+    if total % 2 == 1 {
+        nth(total / 2)
+    } else {
+        (nth(total / 2 - 1) + nth(total / 2)) / 2.0
+    }
+}
+
+impl CsvStats {
+    pub fn to_table(&self) -> String {
+        let header = [
+            "Column", "Type", "Nulls", "Distinct", "Min", "Max", "Mean", "Median", "Top", "Max Len",
+        ]
+        .map(String::from);
+        let text = |v: &Option<Value>| match v {
+            Some(Value::String(s)) => s.clone(),
+            Some(v) => v.to_string(),
+            None => String::new(),
+        };
+        let float = |v: Option<f64>| v.map(|v| format!("{:.2}", v)).unwrap_or_default();
+        let rows = self
+            .columns
+            .iter()
+            .map(|c| {
+                let top = c
+                    .top
+                    .iter()
+                    .map(|t| format!("{} ({})", t.value, t.count))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                vec![
+                    c.name.clone(),
+                    c.ty.to_string(),
+                    c.nulls.to_string(),
+                    c.distinct.to_string(),
+                    text(&c.min),
+                    text(&c.max),
+                    float(c.mean),
+                    float(c.median),
+                    top,
+                    c.max_length.to_string(),
+                ]
+            })
+            .collect::<Vec<_>>();
+        format!(
+            "{} rows\n{}",
+            self.rows,
+            render_table(&header, &rows, 40, false)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_csv_stats() -> anyhow::Result<()> {
+        let stats = process_csv_stats("assets/juventus.csv", &CsvDialect::default(), 3)?;
+        assert_eq!(stats.rows, 27);
+        let kit = &stats.columns[4];
+        assert_eq!(kit.name, "Kit Number");
+        assert_eq!(kit.ty, ColumnType::Int);
+        assert_eq!(kit.min, Some(Value::from(1)));
+        assert_eq!(kit.max, Some(Value::from(77)));
+        assert_eq!(kit.nulls, 0);
+
+        let nationality = &stats.columns[3];
+        assert_eq!(nationality.ty, ColumnType::String);
+        assert_eq!(nationality.top[0].value, "Italy");
+        assert!(nationality.mean.is_none());
+
+        let json = serde_json::to_value(&stats)?;
+        assert_eq!(json["columns"][4]["type"], "int");
+        assert!(stats.to_table().starts_with("27 rows\n+"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_stats_large_ints() -> anyhow::Result<()> {
+        let input = std::env::temp_dir().join("rcli_stats_large_ints.csv");
+        std::fs::write(
+            &input,
+            "id\n9007199254740993\n9223372036854775807\n-9007199254740995\n",
+        )?;
+        let stats = process_csv_stats(&input.to_string_lossy(), &CsvDialect::default(), 3)?;
+        let id = &stats.columns[0];
+        assert_eq!(id.ty, ColumnType::Int);
+        assert_eq!(id.min, Some(Value::from(-9007199254740995i64)));
+        assert_eq!(id.max, Some(Value::from(i64::MAX)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_median_of() {
+        assert_eq!(median_of(&[(1.0, 1), (2.0, 1), (10.0, 1)], 3), 2.0);
+        assert_eq!(median_of(&[(1.0, 2), (3.0, 2)], 4), 2.0);
+    }
+}
@@ -0,0 +1,237 @@
+use std::{collections::VecDeque, io::Write};
+
+use csv::StringRecord;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use super::{reader::fit_record, CsvDialect};
+
+#[derive(Debug, Clone)]
+pub struct TableOpts {
+    // only the first / last n rows, tail keeps a ring buffer so memory stays bounded
+    pub head: Option<usize>,
+    pub tail: Option<usize>,
+    // display width of a cell, longer cells are truncated with … or wrapped
+    pub max_width: usize,
+    pub wrap: bool,
+}
+
+impl Default for TableOpts {
+    fn default() -> Self {
+        Self {
+            head: None,
+            tail: None,
+            max_width: 30,
+            wrap: false,
+        }
+    }
+}
+
+pub fn process_csv_show(
+    input: &str,
+    dialect: &CsvDialect,
+    opts: &TableOpts,
+    output: &mut dyn Write,
+) -> anyhow::Result<()> {
+    let mut reader = dialect.reader(input)?;
+    let mut header = dialect.headers(&mut reader)?;
+    let mut rows = VecDeque::new();
+    // head stops reading early, the rest of the input is never parsed
+    let head = opts.head.unwrap_or(usize::MAX);
+    for result in reader.records().take(head) {
+        let mut record = result?;
+        fit_record(&mut header, &mut record);
+        rows.push_back(record);
+        if opts.tail.is_some_and(|tail| rows.len() > tail) {
+            rows.pop_front();
+        }
+    }
+    let header = header.iter().map(|h| h.to_owned()).collect::<Vec<_>>();
+    let rows = rows
+        .iter()
+        .map(|r: &StringRecord| {
+            let mut row = r.iter().map(|c| c.to_owned()).collect::<Vec<_>>();
+            row.resize(header.len(), String::new());
+            row
+        })
+        .collect::<Vec<_>>();
+    output.write_all(render_table(&header, &rows, opts.max_width, opts.wrap).as_bytes())?;
+    Ok(())
+}
+
+// +--------+----+
+// | Name   | No |
+// +--------+----+
+// | Buffon | 77 |
+// +--------+----+
+pub fn render_table(
+    header: &[String],
+    rows: &[Vec<String>],
+    max_width: usize,
+    wrap: bool,
+) -> String {
+    let max_width = max_width.max(1);
+    let fit = |cell: &str| -> Vec<String> {
+        let cell = cell.replace(['\r', '\n'], " ");
+        if wrap {
+            wrap_cell(&cell, max_width)
+        } else {
+            vec![truncate_cell(&cell, max_width)]
+        }
+    };
+    let header = header.iter().map(|h| fit(h)).collect::<Vec<_>>();
+    let rows = rows
+        .iter()
+        .map(|row| row.iter().map(|c| fit(c)).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    let mut widths = header
+        .iter()
+        .map(|lines| lines.iter().map(|l| l.width()).max().unwrap_or(0))
+        .collect::<Vec<_>>();
+    for row in rows.iter() {
+        for (i, lines) in row.iter().enumerate() {
+            let width = lines.iter().map(|l| l.width()).max().unwrap_or(0);
+            widths[i] = widths[i].max(width);
+        }
+    }
+    // numeric columns read better right aligned
+    let numeric = (0..widths.len())
+        .map(|i| {
+            let mut cells = rows
+                .iter()
+                .map(|r| r[i][0].as_str())
+                .filter(|c| !c.is_empty());
+            cells.clone().next().is_some() && cells.all(|c| c.parse::<f64>().is_ok())
+        })
+        .collect::<Vec<_>>();
+
+    let separator = widths
+        .iter()
+        .map(|w| "-".repeat(w + 2))
+        .collect::<Vec<_>>()
+        .join("+");
+    let separator = format!("+{}+\n", separator);
+    let mut table = separator.clone();
+    push_row(&mut table, &header, &widths, &vec![false; widths.len()]);
+    table.push_str(&separator);
+    for row in rows.iter() {
+        push_row(&mut table, row, &widths, &numeric);
+    }
+    if !rows.is_empty() {
+        table.push_str(&separator);
+    }
+    table
+}
+
+fn push_row(table: &mut String, row: &[Vec<String>], widths: &[usize], right: &[bool]) {
+    let height = row.iter().map(|lines| lines.len()).max().unwrap_or(1);
+    for line in 0..height {
+        table.push('|');
+        for (i, lines) in row.iter().enumerate() {
+            let cell = lines.get(line).map(|s| s.as_str()).unwrap_or_default();
+            let pad = " ".repeat(widths[i] - cell.width());
+            if right[i] {
+                table.push_str(&format!(" {}{} |", pad, cell));
+            } else {
+                table.push_str(&format!(" {}{} |", cell, pad));
+            }
+        }
+        table.push('\n');
+    }
+}
+
+fn truncate_cell(cell: &str, max_width: usize) -> String {
+    if cell.width() <= max_width {
+        return cell.to_owned();
+    }
+    let mut truncated = String::new();
+    let mut width = 0;
+    for c in cell.chars() {
+        let w = c.width().unwrap_or(0);
+        // leave room for the ellipsis
+        if width + w > max_width - 1 {
+            break;
+        }
+        truncated.push(c);
+        width += w;
+    }
+    truncated.push('…');
+    truncated
+}
+
+fn wrap_cell(cell: &str, max_width: usize) -> Vec<String> {
+    let mut lines = vec![String::new()];
+    let mut width = 0;
+    for c in cell.chars() {
+        let w = c.width().unwrap_or(0);
+        if width + w > max_width {
+            lines.push(String::new());
+            width = 0;
+        }
+        lines.last_mut().unwrap().push(c);
+        width += w;
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_table() {
+        let header = vec!["Name".to_string(), "No".to_string()];
+        let rows = vec![
+            vec!["Gianluigi Buffon".to_string(), "77".to_string()],
+            vec!["布冯".to_string(), "1".to_string()],
+        ];
+        let table = render_table(&header, &rows, 10, false);
+        let expected = "\
++------------+----+
+| Name       | No |
++------------+----+
+| Gianluigi… | 77 |
+| 布冯       |  1 |
++------------+----+
+";
+        assert_eq!(table, expected);
+
+        let table = render_table(&header, &rows, 10, true);
+        assert!(table.contains("| Gianluigi  | 77 |\n| Buffon     |    |\n"));
+    }
+
+    #[test]
+    fn test_process_csv_show_head_tail() -> anyhow::Result<()> {
+        let opts = TableOpts {
+            tail: Some(2),
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        process_csv_show(
+            "assets/juventus.csv",
+            &CsvDialect::default(),
+            &opts,
+            &mut output,
+        )?;
+        let table = String::from_utf8(output)?;
+        // 3 separators, header and 2 rows
+        assert_eq!(table.lines().count(), 6);
+        assert!(table.contains("Mario Mandzukic"));
+
+        let opts = TableOpts {
+            head: Some(1),
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        process_csv_show(
+            "assets/juventus.csv",
+            &CsvDialect::default(),
+            &opts,
+            &mut output,
+        )?;
+        let table = String::from_utf8(output)?;
+        assert_eq!(table.lines().count(), 5);
+        assert!(table.contains("Wojciech Szczesny"));
+        Ok(())
+    }
+}
@@ -0,0 +1,309 @@
+use std::{fmt::Write, str::FromStr};
+
+use anyhow::anyhow;
+use chrono::NaiveDate;
+use csv::StringRecord;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::read_content;
+
+use super::infer::is_null;
+
+// transforms:
+//   - column: DOB
+//     op: date
+//     from: "%b %d, %Y"
+//     to: "%Y-%m-%d"
+//   - column: Name
+//     op: regex_replace
+//     pattern: "\\s+"
+//     replacement: " "
+#[derive(Debug, Clone, Deserialize)]
+pub struct TransformRule {
+    pub column: String,
+    #[serde(flatten)]
+    pub transform: Transform,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Transform {
+    Trim,
+    Lower,
+    Upper,
+    // text after the date is ignored, e.g. the age in "Apr 18, 1990 (29)"
+    Date {
+        from: String,
+        to: String,
+    },
+    // $1 or ${name} refer to capture groups
+    RegexReplace {
+        pattern: String,
+        replacement: String,
+    },
+    // new columns are appended to the header, the last one gets the rest of the value
+    Split {
+        separator: String,
+        into: Vec<String>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct TransformFile {
+    transforms: Vec<TransformRule>,
+}
+
+impl TransformRule {
+    pub fn load(path: &str) -> anyhow::Result<Vec<Self>> {
+        let content = read_content(path)?;
+        let file: TransformFile = serde_yaml::from_str(&content)?;
+        Ok(file.transforms)
+    }
+}
+
+// DOB=date:%b %d, %Y->%Y-%m-%d, Name=trim, Name=regex_replace:\s+-> ,
+// DOB=split: (->Born,Age
+impl FromStr for TransformRule {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (column, rule) = s.split_once('=').ok_or("Must be <column>=<op>[:<args>]")?;
+        let column = column.trim();
+        if column.is_empty() {
+            return Err("Column name can not be empty");
+        }
+        let (op, args) = match rule.split_once(':') {
+            Some((op, args)) => (op.trim(), Some(args)),
+            None => (rule.trim(), None),
+        };
+        let arrow = |args: Option<&str>| {
+            args.and_then(|a| a.split_once("->"))
+                .map(|(a, b)| (a.to_owned(), b.to_owned()))
+                .ok_or("Arguments must be <a>-><b>")
+        };
+        let transform = match op {
+            "trim" => Transform::Trim,
+            "lower" => Transform::Lower,
+            "upper" => Transform::Upper,
+            "date" => {
+                let (from, to) = arrow(args)?;
+                Transform::Date { from, to }
+            }
+            "regex_replace" => {
+                let (pattern, replacement) = arrow(args)?;
+                Transform::RegexReplace {
+                    pattern,
+                    replacement,
+                }
+            }
+            "split" => {
+                let (separator, into) = arrow(args)?;
+                let into = into.split(',').map(|c| c.trim().to_owned()).collect();
+                Transform::Split { separator, into }
+            }
+            _ => return Err("Op must be trim, lower, upper, date, regex_replace or split"),
+        };
+        Ok(Self {
+            column: column.to_owned(),
+            transform,
+        })
+    }
+}
+
+#[derive(Debug)]
+enum Op {
+    Trim,
+    Lower,
+    Upper,
+    Date { from: String, to: String },
+    RegexReplace { regex: Regex, replacement: String },
+    Split { separator: String, columns: usize },
+}
+
+// rules resolved against the header in order, so a rule can use a column an earlier split created
+#[derive(Debug, Default)]
+pub struct Transformer {
+    // number of input columns, split columns come right after them
+    width: usize,
+    header: StringRecord,
+    ops: Vec<(usize, Op)>,
+}
+
+impl Transformer {
+    pub fn new(header: &StringRecord, rules: &[TransformRule]) -> anyhow::Result<Self> {
+        let mut names = header.iter().map(String::from).collect::<Vec<_>>();
+        let mut ops = Vec::new();
+        for rule in rules {
+            let index = names
+                .iter()
+                .position(|h| *h == rule.column)
+                .ok_or(anyhow!("column {} does not exist", rule.column))?;
+            let op = match &rule.transform {
+                Transform::Trim => Op::Trim,
+                Transform::Lower => Op::Lower,
+                Transform::Upper => Op::Upper,
+                Transform::Date { from, to } => Op::Date {
+                    from: from.clone(),
+                    to: to.clone(),
+                },
+                Transform::RegexReplace {
+                    pattern,
+                    replacement,
+                } => Op::RegexReplace {
+                    regex: Regex::new(pattern)
+                        .map_err(|e| anyhow!("column {}: invalid pattern: {}", rule.column, e))?,
+                    replacement: replacement.clone(),
+                },
+                Transform::Split { separator, into } => {
+                    if separator.is_empty() || into.is_empty() {
+                        return Err(anyhow!(
+                            "column {}: split needs a separator and new column names",
+                            rule.column
+                        ));
+                    }
+                    for name in into {
+                        if names.contains(name) {
+                            return Err(anyhow!("split column {} already exists", name));
+                        }
+                        names.push(name.clone());
+                    }
+                    Op::Split {
+                        separator: separator.clone(),
+                        columns: into.len(),
+                    }
+                }
+            };
+            ops.push((index, op));
+        }
+        Ok(Self {
+            width: header.len(),
+            header: names.into_iter().collect(),
+            ops,
+        })
+    }
+
+    pub fn header(&self) -> StringRecord {
+        self.header.clone()
+    }
+
+    // cells of flexible rows beyond the input header are kept after the split columns
+    pub fn apply(&self, record: StringRecord) -> anyhow::Result<StringRecord> {
+        if self.ops.is_empty() {
+            return Ok(record);
+        }
+        let mut cells = (0..self.width)
+            .map(|i| record.get(i).unwrap_or_default().to_owned())
+            .collect::<Vec<_>>();
+        for (index, op) in self.ops.iter() {
+            let value = &cells[*index];
+            let transformed = match op {
+                Op::Trim => value.trim().to_owned(),
+                Op::Lower => value.to_lowercase(),
+                Op::Upper => value.to_uppercase(),
+                Op::Date { .. } if is_null(value) => continue,
+                Op::Date { from, to } => reformat_date(value, from, to).map_err(|e| {
+                    let line = record.position().map(|p| p.line()).unwrap_or_default();
+                    anyhow!("line {}, column {}: {}", line, &self.header[*index], e)
+                })?,
+                Op::RegexReplace { regex, replacement } => {
+                    regex.replace_all(value, replacement.as_str()).into_owned()
+                }
+                Op::Split { separator, columns } => {
+                    let mut parts = value
+                        .splitn(*columns, separator.as_str())
+                        .map(String::from)
+                        .collect::<Vec<_>>();
+                    parts.resize(*columns, String::new());
+                    cells.extend(parts);
+                    continue;
+                }
+            };
+            cells[*index] = transformed;
+        }
+        let extra = record.iter().skip(self.width).map(String::from);
+        let mut transformed = cells.into_iter().chain(extra).collect::<StringRecord>();
+        transformed.set_position(record.position().cloned());
+        Ok(transformed)
+    }
+}
+
+fn reformat_date(value: &str, from: &str, to: &str) -> anyhow::Result<String> {
+    let (date, _) = NaiveDate::parse_and_remainder(value.trim(), from)
+        .map_err(|e| anyhow!("{:?} does not match {}: {}", value, from, e))?;
+    let mut formatted = String::new();
+    // a format asking for a time of day can not be filled from a date
+    write!(formatted, "{}", date.format(to)).map_err(|_| anyhow!("invalid date format {}", to))?;
+    Ok(formatted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(rules: &[&str]) -> anyhow::Result<Vec<TransformRule>> {
+        rules
+            .iter()
+            .map(|r| r.parse().map_err(anyhow::Error::msg))
+            .collect()
+    }
+
+    #[test]
+    fn test_transformer() -> anyhow::Result<()> {
+        let header = StringRecord::from(vec!["Name", "DOB"]);
+        let rules = rules(&[
+            "DOB=split: (->Born,Age",
+            "Born=date:%b %d, %Y->%Y-%m-%d",
+            "DOB=date:%b %d, %Y->%d/%m/%Y",
+            "Age=regex_replace:\\)->",
+            "Name=trim",
+            "Name=upper",
+        ])?;
+        let transformer = Transformer::new(&header, &rules)?;
+        assert_eq!(transformer.header(), vec!["Name", "DOB", "Born", "Age"]);
+        let record = StringRecord::from(vec![" Mattia Perin ", "Nov 10, 1992 (26)"]);
+        assert_eq!(
+            transformer.apply(record)?,
+            vec!["MATTIA PERIN", "10/11/1992", "1992-11-10", "26"]
+        );
+        let record = StringRecord::from(vec!["x", "not a date"]);
+        assert!(transformer.apply(record).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_transformer_order() -> anyhow::Result<()> {
+        let header = StringRecord::from(vec!["DOB"]);
+        // rules run in order, so the date is reformatted after the split copied it
+        let rules = rules(&["DOB=split:, ->Day,Year", "DOB=date:%b %d, %Y->%Y"])?;
+        let transformer = Transformer::new(&header, &rules)?;
+        let record = StringRecord::from(vec!["Apr 18, 1990 (29)"]);
+        assert_eq!(
+            transformer.apply(record)?,
+            vec!["1990", "Apr 18", "1990 (29)"]
+        );
+        assert!(Transformer::new(&header, &self::rules(&["Born=trim"])?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_transform_rule() {
+        assert!("DOB=date:%Y".parse::<TransformRule>().is_err());
+        assert!("DOB=reverse".parse::<TransformRule>().is_err());
+        assert!("=trim".parse::<TransformRule>().is_err());
+    }
+
+    #[test]
+    fn test_load_transform_rules() -> anyhow::Result<()> {
+        let rules = TransformRule::load("fixtures/juventus.transforms.yaml")?;
+        assert!(matches!(rules[0].transform, Transform::Date { .. }));
+        let header = StringRecord::from(vec!["Name", "Position", "DOB"]);
+        let transformer = Transformer::new(&header, &rules)?;
+        let record =
+            StringRecord::from(vec!["Leonardo Bonucci ", "Centre-Back", "May 1, 1987 (32)"]);
+        assert_eq!(
+            transformer.apply(record)?,
+            vec!["Leonardo Bonucci", "centre back", "1987-05-01"]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,345 @@
+use std::{borrow::Cow, collections::BTreeMap, io::Write};
+
+use anyhow::anyhow;
+use csv::StringRecord;
+use serde_json::{Map, Value};
+
+use crate::cli::{ColumnType, OutputFormat};
+
+use super::{
+    encoding::EncodeWriter,
+    markup::{self, HtmlWriter, MarkdownWriter},
+    proto,
+    sql::{self, SqlWriter, SqliteWriter},
+    CsvConvertOpts,
+};
+
+pub type Row = Map<String, Value>;
+
+// every output format is written one row at a time, so conversion runs in constant memory;
+// finish flushes, which matters for writers that own a file
+pub trait RowWriter {
+    // called once before the first row, with the output header and its final types
+    fn begin(
+        &mut self,
+        _header: &StringRecord,
+        _types: &[Option<ColumnType>],
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+    // called instead of begin when rows are appended to output an earlier writer began,
+    // e.g. a split part that was closed to keep the number of open files down
+    fn resume(
+        &mut self,
+        _header: &StringRecord,
+        _types: &[Option<ColumnType>],
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+    fn write_row(&mut self, row: &Row) -> anyhow::Result<()>;
+    fn finish(&mut self) -> anyhow::Result<()>;
+}
+
+pub fn row_writer<'a>(
+    opts: &CsvConvertOpts,
+    input: &str,
+    w: impl Write + 'a,
+) -> anyhow::Result<Box<dyn RowWriter + 'a>> {
+    let table = match &opts.sql_table {
+        Some(table) => table.clone(),
+        None => sql::table_name(input),
+    };
+    if let Some(db) = &opts.sqlite_db {
+        if !opts.group_by.is_empty() || opts.output_encoding.is_some() {
+            return Err(anyhow!(
+                "--sqlite-db can not be used with --group-by or --output-encoding"
+            ));
+        }
+        return Ok(Box::new(SqliteWriter::open(db, &table)?));
+    }
+    let w: Box<dyn Write + 'a> = match opts.output_encoding {
+        Some(_) if matches!(opts.format, OutputFormat::Proto) => {
+            return Err(anyhow!("proto output is binary and can not be re-encoded"));
+        }
+        Some(encoding) => Box::new(EncodeWriter::new(w, encoding)),
+        None => Box::new(w),
+    };
+    if !opts.group_by.is_empty() {
+        if !matches!(opts.format, OutputFormat::Json | OutputFormat::Yaml) {
+            return Err(anyhow!("--group-by needs json or yaml output"));
+        }
+        return Ok(Box::new(GroupWriter {
+            w,
+            format: opts.format,
+            keys: opts.group_by.clone(),
+            doc: Map::new(),
+        }));
+    }
+    let writer: Box<dyn RowWriter + 'a> = match opts.format {
+        OutputFormat::Json => Box::new(JsonWriter { w, count: 0 }),
+        OutputFormat::Ndjson => Box::new(NdjsonWriter { w }),
+        OutputFormat::Yaml => Box::new(YamlWriter { w, count: 0 }),
+        OutputFormat::Toml => {
+            if opts.toml_table.is_empty() {
+                return Err(anyhow!("TOML table name can not be empty"));
+            }
+            Box::new(TomlWriter {
+                w,
+                table: opts.toml_table.clone(),
+                keep_empty: opts.toml_keep_empty,
+                count: 0,
+            })
+        }
+        OutputFormat::Proto => Box::new(ProtoWriter { w, buf: Vec::new() }),
+        OutputFormat::Csv => Box::new(CsvWriter {
+            w: csv::Writer::from_writer(w),
+            header: false,
+        }),
+        OutputFormat::Markdown => Box::new(MarkdownWriter { w }),
+        OutputFormat::Html => Box::new(HtmlWriter {
+            w,
+            title: markup::title(input),
+            numeric: Vec::new(),
+        }),
+        OutputFormat::Sql => {
+            if opts.sql_batch == 0 {
+                return Err(anyhow!("--sql-batch must be greater than 0"));
+            }
+            Box::new(SqlWriter {
+                w,
+                dialect: opts.sql_dialect,
+                table,
+                batch: opts.sql_batch,
+                columns: Vec::new(),
+                rows: Vec::new(),
+            })
+        }
+    };
+    Ok(writer)
+}
+
+// same layout as serde_json::to_string_pretty on a Vec of rows
+struct JsonWriter<'a> {
+    w: Box<dyn Write + 'a>,
+    count: usize,
+}
+
+impl RowWriter for JsonWriter<'_> {
+    fn write_row(&mut self, row: &Row) -> anyhow::Result<()> {
+        self.w
+            .write_all(if self.count == 0 { b"[\n" } else { b",\n" })?;
+        // json strings never contain raw newlines, so indenting line by line is safe
+        let json = serde_json::to_string_pretty(row)?;
+        for (i, line) in json.lines().enumerate() {
+            if i > 0 {
+                self.w.write_all(b"\n")?;
+            }
+            write!(self.w, "  {}", line)?;
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.w
+            .write_all(if self.count == 0 { b"[]" } else { b"\n]" })?;
+        self.w.flush()?;
+        Ok(())
+    }
+}
+
+struct NdjsonWriter<'a> {
+    w: Box<dyn Write + 'a>,
+}
+
+impl RowWriter for NdjsonWriter<'_> {
+    fn write_row(&mut self, row: &Row) -> anyhow::Result<()> {
+        serde_json::to_writer(&mut self.w, row)?;
+        self.w.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.w.flush()?;
+        Ok(())
+    }
+}
+
+// a one element sequence serializes to exactly one `- ...` item of the full document
+struct YamlWriter<'a> {
+    w: Box<dyn Write + 'a>,
+    count: usize,
+}
+
+impl RowWriter for YamlWriter<'_> {
+    fn write_row(&mut self, row: &Row) -> anyhow::Result<()> {
+        self.w
+            .write_all(serde_yaml::to_string(&[row])?.as_bytes())?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        if self.count == 0 {
+            self.w.write_all(b"[]\n")?;
+        }
+        self.w.flush()?;
+        Ok(())
+    }
+}
+
+struct TomlWriter<'a> {
+    w: Box<dyn Write + 'a>,
+    table: String,
+    keep_empty: bool,
+    count: usize,
+}
+
+impl RowWriter for TomlWriter<'_> {
+    fn write_row(&mut self, row: &Row) -> anyhow::Result<()> {
+        // TOML has no null, so null and empty cells are dropped or written as ""
+        let row = row
+            .iter()
+            .filter_map(|(k, v)| match v {
+                Value::Null if self.keep_empty => Some((k.clone(), Value::String(String::new()))),
+                Value::Null => None,
+                Value::String(s) if s.is_empty() && !self.keep_empty => None,
+                v => Some((k.clone(), v.clone())),
+            })
+            .collect::<Row>();
+        if self.count > 0 {
+            self.w.write_all(b"\n")?;
+        }
+        self.w
+            .write_all(toml_document(&self.table, vec![row])?.as_bytes())?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        if self.count == 0 {
+            self.w
+                .write_all(toml_document(&self.table, Vec::new())?.as_bytes())?;
+        }
+        self.w.flush()?;
+        Ok(())
+    }
+}
+
+fn toml_document(table: &str, rows: Vec<Row>) -> anyhow::Result<String> {
+    let mut doc = BTreeMap::new();
+    doc.insert(table, rows);
+    Ok(toml::to_string(&doc)?)
+}
+
+// proto fields are all strings and numbered in header order, so typed cells are written
+// in their string form
+struct ProtoWriter<'a> {
+    w: Box<dyn Write + 'a>,
+    buf: Vec<u8>,
+}
+
+impl RowWriter for ProtoWriter<'_> {
+    fn write_row(&mut self, row: &Row) -> anyhow::Result<()> {
+        self.buf.clear();
+        let values = row
+            .values()
+            .map(|v| match v {
+                Value::Null => Cow::Borrowed(""),
+                Value::String(s) => Cow::Borrowed(s.as_str()),
+                v => Cow::Owned(v.to_string()),
+            })
+            .collect::<Vec<_>>();
+        proto::encode_delimited(values.iter().map(|v| v.as_ref()), &mut self.buf);
+        self.w.write_all(&self.buf)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.w.flush()?;
+        Ok(())
+    }
+}
+
+// the header comes from the first row, so an empty result writes nothing
+struct CsvWriter<'a> {
+    w: csv::Writer<Box<dyn Write + 'a>>,
+    header: bool,
+}
+
+impl RowWriter for CsvWriter<'_> {
+    fn resume(&mut self, _: &StringRecord, _: &[Option<ColumnType>]) -> anyhow::Result<()> {
+        self.header = true;
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &Row) -> anyhow::Result<()> {
+        if !self.header {
+            self.w.write_record(row.keys())?;
+            self.header = true;
+        }
+        self.w.write_record(row.values().map(|v| match v {
+            Value::Null => String::new(),
+            Value::String(s) => s.clone(),
+            v => v.to_string(),
+        }))?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.w.flush()?;
+        Ok(())
+    }
+}
+
+// {"Italy": {"Goalkeeper": [row, ...]}}, groups keep the order they are first seen in,
+// so every row is held until the whole document can be written
+struct GroupWriter<'a> {
+    w: Box<dyn Write + 'a>,
+    format: OutputFormat,
+    keys: Vec<String>,
+    doc: Map<String, Value>,
+}
+
+impl RowWriter for GroupWriter<'_> {
+    fn write_row(&mut self, row: &Row) -> anyhow::Result<()> {
+        let names = self
+            .keys
+            .iter()
+            .map(|key| match row.get(key) {
+                Some(Value::String(s)) => Ok(s.clone()),
+                Some(value) => Ok(value.to_string()),
+                None => Err(anyhow!("column {} does not exist", key)),
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let (last, parents) = names.split_last().expect("group_by is not empty");
+        let mut group = &mut self.doc;
+        for name in parents {
+            group = group
+                .entry(name.clone())
+                .or_insert_with(|| Value::Object(Map::new()))
+                .as_object_mut()
+                .expect("outer groups are objects");
+        }
+        if let Value::Array(rows) = group
+            .entry(last.clone())
+            .or_insert_with(|| Value::Array(Vec::new()))
+        {
+            rows.push(Value::Object(row.clone()));
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        match self.format {
+            OutputFormat::Yaml => self
+                .w
+                .write_all(serde_yaml::to_string(&self.doc)?.as_bytes())?,
+            _ => self
+                .w
+                .write_all(serde_json::to_string_pretty(&self.doc)?.as_bytes())?,
+        }
+        self.w.flush()?;
+        Ok(())
+    }
+}
@@ -1,6 +1,6 @@
 use base64::prelude::*;
 use chacha20poly1305::{
-    aead::{Aead, AeadCore, Key, KeyInit, Nonce, OsRng},
+    aead::{Aead, AeadCore, Key, KeyInit, Nonce, OsRng, Payload},
     XChaCha20Poly1305,
 };
 
@@ -19,6 +19,55 @@ pub trait TextDecrypor {
 
 pub struct ChaCha20Poly1305 {}
 
+// a nonce is as long as XChaCha20 needs, so random nonces can be used for every message
+const NONCE_LEN: usize = 24;
+
+impl ChaCha20Poly1305 {
+    pub fn generate_key() -> String {
+        BASE64_STANDARD.encode(XChaCha20Poly1305::generate_key(&mut OsRng))
+    }
+
+    // encrypts with a fresh nonce, returned in front of the ciphertext as one base64 string;
+    // the ciphertext only opens with the same associated data, e.g. the column it was in
+    pub fn seal(data: &str, key_base64: &str, aad: &[u8]) -> anyhow::Result<String> {
+        let cipher = Self::cipher(key_base64)?;
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: data.as_bytes(),
+            aad,
+        };
+        let ciphertext = cipher
+            .encrypt(&nonce, payload)
+            .map_err(|e| anyhow::anyhow!(format!("{}", e)))?;
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        Ok(BASE64_STANDARD.encode(sealed))
+    }
+
+    pub fn open(sealed: &str, key_base64: &str, aad: &[u8]) -> anyhow::Result<String> {
+        let cipher = Self::cipher(key_base64)?;
+        let sealed = BASE64_STANDARD.decode(sealed)?;
+        if sealed.len() < NONCE_LEN {
+            return Err(anyhow::anyhow!("ciphertext is too short"));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let payload = Payload {
+            msg: ciphertext,
+            aad,
+        };
+        let plaintext = cipher
+            .decrypt(Nonce::<XChaCha20Poly1305>::from_slice(nonce), payload)
+            .map_err(|e| anyhow::anyhow!(format!("{}", e)))?;
+        Ok(String::from_utf8(plaintext)?)
+    }
+
+    fn cipher(key_base64: &str) -> anyhow::Result<XChaCha20Poly1305> {
+        let key_bytes = BASE64_STANDARD.decode(key_base64.trim())?;
+        XChaCha20Poly1305::new_from_slice(&key_bytes)
+            .map_err(|_| anyhow::anyhow!("key must be 32 bytes encoded as base64"))
+    }
+}
+
 impl TextEncryptor for ChaCha20Poly1305 {
     fn encrypt(data: &str) -> anyhow::Result<EncryptResult> {
         let key = XChaCha20Poly1305::generate_key(&mut OsRng);
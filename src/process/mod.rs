@@ -10,7 +10,14 @@ mod text;
 
 // pub use 导出
 pub use base64::*;
-pub use csv::process_csv;
+pub use csv::{
+    process_csv, process_csv_decrypt, process_csv_diff, process_csv_encrypt, process_csv_from,
+    process_csv_generate_key, process_csv_join, process_csv_pivot, process_csv_show,
+    process_csv_split, process_csv_stats, process_csv_validate, process_csv_write_quarantine,
+    CellChange, ChangedRow, ColumnRule, ColumnStats, CsvConvertOpts, CsvDialect, CsvDiff,
+    CsvOutput, CsvPivot, CsvSchema, CsvStats, JoinOpts, PivotOpts, Predicate, SortKey, SplitBy,
+    SplitFile, SplitOpts, TableOpts, Transform, TransformRule, Violation,
+};
 pub use gen_pass::process_gen_pass;
 pub use http::*;
 pub use jwt::*;
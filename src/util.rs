@@ -1,8 +1,11 @@
 use std::{
     fs::File,
-    io::{stdin, Read},
+    io::{self, stdin, stdout, BufRead, BufReader, BufWriter, Read, Write},
+    path::Path,
 };
 
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression as GzLevel};
+
 // windows: use ctrl+z to finish stdin input
 pub fn read_content(input: &str) -> anyhow::Result<String> {
     let mut reader: Box<dyn Read> = if input == "-" {
@@ -15,3 +18,187 @@ pub fn read_content(input: &str) -> anyhow::Result<String> {
     reader.read_to_end(&mut buffer)?;
     Ok(String::from_utf8_lossy(&buffer).trim().to_owned())
 }
+
+// the input exactly as it is, unlike read_content nothing is trimmed or replaced
+pub fn read_text(input: &str) -> anyhow::Result<String> {
+    let mut reader: Box<dyn Read> = if input == "-" {
+        Box::new(stdin())
+    } else {
+        Box::new(File::open(input)?)
+    };
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer)?;
+    String::from_utf8(buffer).map_err(|e| {
+        anyhow::anyhow!(
+            "{} is not valid UTF-8 at byte {}",
+            input,
+            e.utf8_error().valid_up_to()
+        )
+    })
+}
+
+// "-" writes to stdout, files ending in .gz or .zst are compressed;
+// finish has to be called once everything is written
+pub fn create_output(output: &str) -> anyhow::Result<OutputWriter<Box<dyn Write>>> {
+    let writer: Box<dyn Write> = if output == "-" {
+        Box::new(BufWriter::new(stdout().lock()))
+    } else {
+        Box::new(BufWriter::new(File::create(output)?))
+    };
+    Ok(compress_writer(output, writer)?)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compression {
+    Gzip,
+    Zstd,
+}
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+fn compression_of(path: &str) -> Option<Compression> {
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("gz") => Some(Compression::Gzip),
+        Some("zst") => Some(Compression::Zstd),
+        _ => None,
+    }
+}
+
+pub fn compress_writer<W: Write>(path: &str, w: W) -> io::Result<OutputWriter<W>> {
+    let encoder = match compression_of(path) {
+        Some(Compression::Gzip) => Encoder::Gzip(GzEncoder::new(w, GzLevel::default())),
+        Some(Compression::Zstd) => Encoder::Zstd(zstd::Encoder::new(w, 0)?),
+        None => Encoder::Plain(w),
+    };
+    Ok(OutputWriter {
+        encoder,
+        finished: false,
+    })
+}
+
+enum Encoder<W: Write> {
+    Plain(W),
+    Gzip(GzEncoder<W>),
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+pub struct OutputWriter<W: Write> {
+    encoder: Encoder<W>,
+    finished: bool,
+}
+
+impl<W: Write> OutputWriter<W> {
+    // ends a compressed stream with its trailer, so a full disk or a closed pipe is an error
+    // instead of being lost on drop; nothing can be written afterwards
+    pub fn finish(&mut self) -> io::Result<()> {
+        if !self.finished {
+            match &mut self.encoder {
+                Encoder::Plain(_) => {}
+                Encoder::Gzip(e) => e.try_finish()?,
+                Encoder::Zstd(e) => e.do_finish()?,
+            }
+            self.finished = true;
+        }
+        self.inner().flush()
+    }
+
+    fn inner(&mut self) -> &mut W {
+        match &mut self.encoder {
+            Encoder::Plain(w) => w,
+            Encoder::Gzip(e) => e.get_mut(),
+            Encoder::Zstd(e) => e.get_mut(),
+        }
+    }
+}
+
+impl<W: Write> Write for OutputWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.finished {
+            return Err(io::Error::other("output is already finished"));
+        }
+        match &mut self.encoder {
+            Encoder::Plain(w) => w.write(buf),
+            Encoder::Gzip(e) => e.write(buf),
+            Encoder::Zstd(e) => e.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.finished {
+            return self.inner().flush();
+        }
+        match &mut self.encoder {
+            Encoder::Plain(w) => w.flush(),
+            Encoder::Gzip(e) => e.flush(),
+            Encoder::Zstd(e) => e.flush(),
+        }
+    }
+}
+
+// the magic bytes win over the extension, so stdin and misnamed files work too
+pub fn decompress_reader(path: &str, rdr: Box<dyn Read>) -> io::Result<Box<dyn Read>> {
+    let mut rdr = BufReader::new(rdr);
+    let head = rdr.fill_buf()?;
+    let compression = if head.starts_with(GZIP_MAGIC) {
+        Some(Compression::Gzip)
+    } else if head.starts_with(ZSTD_MAGIC) {
+        Some(Compression::Zstd)
+    } else {
+        compression_of(path)
+    };
+    Ok(match compression {
+        Some(Compression::Gzip) => Box::new(MultiGzDecoder::new(rdr)),
+        Some(Compression::Zstd) => Box::new(zstd::Decoder::with_buffer(rdr)?),
+        None => Box::new(rdr),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // takes a few bytes, then fails like a full disk
+    struct Full(usize);
+
+    impl Write for Full {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.0 == 0 {
+                return Err(io::Error::other("no space left on device"));
+            }
+            let n = buf.len().min(self.0);
+            self.0 -= n;
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_compress_writer_reports_finish_errors() -> io::Result<()> {
+        for path in ["out.csv.gz", "out.csv.zst"] {
+            let mut w = compress_writer(path, Full(12))?;
+            let result = w.write_all(b"Name,Kit Number\n").and_then(|_| w.finish());
+            assert!(result.is_err(), "{}", path);
+        }
+
+        for path in ["out.csv.gz", "out.csv.zst"] {
+            let mut buf = Vec::new();
+            let mut w = compress_writer(path, &mut buf)?;
+            // a flush in between does not end the stream
+            w.write_all(b"Name,Kit Number\n")?;
+            w.flush()?;
+            w.write_all(b"Paulo Dybala,10\n")?;
+            w.finish()?;
+            w.finish()?;
+            assert!(w.write_all(b"more").is_err());
+            drop(w);
+            let mut decoded = String::new();
+            decompress_reader("-", Box::new(io::Cursor::new(buf)))?.read_to_string(&mut decoded)?;
+            assert_eq!(decoded, "Name,Kit Number\nPaulo Dybala,10\n", "{}", path);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,38 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+// stdin is typed from its first 1000 rows, a later cell that does not fit stays a string
+#[test]
+fn csv_stdin_beyond_infer_sample() -> anyhow::Result<()> {
+    let mut input = String::from("v\n");
+    for i in 0..1200 {
+        input.push_str(&format!("{}\n", i));
+    }
+    input.push_str("abc\n");
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rcli"))
+        .args(["csv", "-f", "ndjson", "-o", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .expect("piped stdin")
+        .write_all(input.as_bytes())?;
+    let output = child.wait_with_output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8(output.stdout)?;
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 1201);
+    assert_eq!(lines[1199], r#"{"v":1199}"#);
+    assert_eq!(lines[1200], r#"{"v":"abc"}"#);
+    Ok(())
+}